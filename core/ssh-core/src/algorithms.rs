@@ -0,0 +1,394 @@
+//! Списки предпочтений алгоритмов, именованные профили и вычисление согласованных алгоритмов.
+//!
+//! russh не отдаёт итог согласования наружу, поэтому он вычисляется по RFC 4253 §7.1
+//! из двух открытых `SSH_MSG_KEXINIT`, перехваченных на сокете (см. `wire`).
+
+use crate::{SshError, SshErrorCode};
+use russh::{cipher, compression, kex, mac, Preferred};
+use std::borrow::Cow;
+
+/// Именованный набор предпочтений
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CryptoProfile {
+    /// Только curve25519, ed25519/ECDSA/rsa-sha2, chacha20-poly1305/AES-GCM и ETM-MAC
+    Modern,
+    /// `Modern` плюс ECDH NIST, DH group14/16 на SHA-2, AES-CTR и MAC без ETM
+    #[default]
+    Compatible,
+    /// Старые серверы: добавляет SHA-1 (`ssh-rsa`, DH group1/14-sha1, `hmac-sha1`) и CBC
+    Legacy,
+}
+
+/// Списки в порядке убывания предпочтения (имена SSH, например `curve25519-sha256`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlgorithmPreferences {
+    pub kex: Vec<String>,
+    pub host_key: Vec<String>,
+    pub cipher: Vec<String>,
+    pub mac: Vec<String>,
+    pub compression: Vec<String>,
+}
+
+const MODERN_KEX: &[&str] = &["curve25519-sha256", "curve25519-sha256@libssh.org"];
+const COMPATIBLE_KEX: &[&str] = &[
+    "ecdh-sha2-nistp256",
+    "ecdh-sha2-nistp384",
+    "ecdh-sha2-nistp521",
+    "diffie-hellman-group16-sha512",
+    "diffie-hellman-group14-sha256",
+];
+const LEGACY_KEX: &[&str] = &["diffie-hellman-group14-sha1", "diffie-hellman-group1-sha1"];
+
+const MODERN_HOST_KEY: &[&str] = &[
+    "ssh-ed25519",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "rsa-sha2-512",
+    "rsa-sha2-256",
+];
+const LEGACY_HOST_KEY: &[&str] = &["ssh-rsa"];
+
+const MODERN_CIPHER: &[&str] = &["chacha20-poly1305@openssh.com", "aes256-gcm@openssh.com"];
+const COMPATIBLE_CIPHER: &[&str] = &["aes256-ctr", "aes192-ctr", "aes128-ctr"];
+const LEGACY_CIPHER: &[&str] = &["aes256-cbc", "aes192-cbc", "aes128-cbc", "3des-cbc"];
+
+const MODERN_MAC: &[&str] = &[
+    "hmac-sha2-512-etm@openssh.com",
+    "hmac-sha2-256-etm@openssh.com",
+];
+const COMPATIBLE_MAC: &[&str] = &["hmac-sha2-512", "hmac-sha2-256"];
+const LEGACY_MAC: &[&str] = &["hmac-sha1-etm@openssh.com", "hmac-sha1"];
+
+impl CryptoProfile {
+    /// Имя профиля в конфигурации: `modern`, `compatible`, `legacy`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "modern" => Some(CryptoProfile::Modern),
+            "compatible" => Some(CryptoProfile::Compatible),
+            "legacy" => Some(CryptoProfile::Legacy),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CryptoProfile::Modern => "modern",
+            CryptoProfile::Compatible => "compatible",
+            CryptoProfile::Legacy => "legacy",
+        }
+    }
+
+    pub fn preferences(self) -> AlgorithmPreferences {
+        let tiers = match self {
+            CryptoProfile::Modern => 1,
+            CryptoProfile::Compatible => 2,
+            CryptoProfile::Legacy => 3,
+        };
+        let list = |parts: [&[&str]; 3]| -> Vec<String> {
+            parts[..tiers]
+                .iter()
+                .flat_map(|p| p.iter().map(|s| s.to_string()))
+                .collect()
+        };
+        AlgorithmPreferences {
+            kex: list([MODERN_KEX, COMPATIBLE_KEX, LEGACY_KEX]),
+            host_key: list([MODERN_HOST_KEY, &[], LEGACY_HOST_KEY]),
+            cipher: list([MODERN_CIPHER, COMPATIBLE_CIPHER, LEGACY_CIPHER]),
+            mac: list([MODERN_MAC, COMPATIBLE_MAC, LEGACY_MAC]),
+            compression: vec!["none".to_string()],
+        }
+    }
+}
+
+/// `zlib@openssh.com` включает сжатие только после аутентификации и предпочтительнее `zlib`
+const COMPRESSION_ENABLED: &[&str] = &["zlib@openssh.com", "zlib", "none"];
+
+impl Default for AlgorithmPreferences {
+    fn default() -> Self {
+        CryptoProfile::default().preferences()
+    }
+}
+
+impl AlgorithmPreferences {
+    /// Хост-опция `compression`: со сжатием сервер без zlib всё равно согласует `none`
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        let names: &[&str] = if enabled {
+            COMPRESSION_ENABLED
+        } else {
+            &["none"]
+        };
+        self.compression = names.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Списки для russh; неизвестное russh имя — `BadRequest`, а не молчаливый пропуск
+    pub(crate) fn to_preferred(&self) -> Result<Preferred, SshError> {
+        let mut kex_names = parse_names(&self.kex, "kex", |s| kex::Name::try_from(s).ok())?;
+        // Маркеры расширений: без kex-strict клиент уязвим к Terrapin (CVE-2023-48795)
+        kex_names.push(kex::EXTENSION_SUPPORT_AS_CLIENT);
+        kex_names.push(kex::EXTENSION_OPENSSH_STRICT_KEX_AS_CLIENT);
+        let key = parse_names(&self.host_key, "host key", |s| {
+            ssh_key::Algorithm::new(s).ok()
+        })?;
+        let cipher = parse_names(&self.cipher, "cipher", |s| {
+            cipher::Name::try_from(s)
+                .ok()
+                .filter(|n| *n != cipher::NONE && *n != cipher::CLEAR)
+        })?;
+        let mac = parse_names(&self.mac, "MAC", |s| {
+            mac::Name::try_from(s).ok().filter(|n| *n != mac::NONE)
+        })?;
+        let compression = parse_names(&self.compression, "compression", |s| {
+            compression::Name::try_from(s).ok()
+        })?;
+        Ok(Preferred {
+            kex: Cow::Owned(kex_names),
+            key: Cow::Owned(key),
+            cipher: Cow::Owned(cipher),
+            mac: Cow::Owned(mac),
+            compression: Cow::Owned(compression),
+        })
+    }
+}
+
+fn parse_names<T>(
+    names: &[String],
+    kind: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, SshError> {
+    if names.is_empty() {
+        return Err(SshError::new(
+            SshErrorCode::BadRequest,
+            format!("Empty {kind} algorithm list"),
+            false,
+        ));
+    }
+    names
+        .iter()
+        .map(|name| {
+            parse(name).ok_or_else(|| {
+                SshError::new(
+                    SshErrorCode::BadRequest,
+                    format!("Unsupported {kind} algorithm: {name}"),
+                    false,
+                )
+            })
+        })
+        .collect()
+}
+
+/// Итог согласования; для AEAD-шифров MAC встроен и указывается как `<implicit>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegotiatedAlgorithms {
+    pub kex: String,
+    pub host_key: String,
+    pub cipher_client_to_server: String,
+    pub cipher_server_to_client: String,
+    pub mac_client_to_server: String,
+    pub mac_server_to_client: String,
+    pub compression_client_to_server: String,
+    pub compression_server_to_client: String,
+}
+
+pub(crate) const IMPLICIT_MAC: &str = "<implicit>";
+
+/// Десять name-list из `SSH_MSG_KEXINIT` в порядке RFC 4253 §7.1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct KexInit {
+    lists: Vec<Vec<String>>,
+}
+
+const MSG_KEXINIT: u8 = 20;
+
+impl KexInit {
+    /// `payload` начинается с кода сообщения
+    pub(crate) fn parse(payload: &[u8]) -> Option<Self> {
+        let (&code, rest) = payload.split_first()?;
+        if code != MSG_KEXINIT || rest.len() < 16 {
+            return None;
+        }
+        let mut rest = &rest[16..];
+        let mut lists = Vec::with_capacity(10);
+        for _ in 0..10 {
+            let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+            let bytes = rest.get(4..4 + len)?;
+            let text = std::str::from_utf8(bytes).ok()?;
+            lists.push(
+                text.split(',')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            );
+            rest = &rest[4 + len..];
+        }
+        Some(Self { lists })
+    }
+
+    fn list(&self, index: usize) -> &[String] {
+        &self.lists[index]
+    }
+}
+
+fn pick(client: &[String], server: &[String]) -> Option<String> {
+    client.iter().find(|c| server.contains(c)).cloned()
+}
+
+fn is_aead(cipher: &str) -> bool {
+    cipher == "chacha20-poly1305@openssh.com" || cipher.ends_with("-gcm@openssh.com")
+}
+
+/// Первый алгоритм клиента, который есть у сервера (RFC 4253 §7.1)
+pub(crate) fn negotiate(client: &KexInit, server: &KexInit) -> Option<NegotiatedAlgorithms> {
+    let both = |i: usize| pick(client.list(i), server.list(i));
+    let cipher_c2s = both(2)?;
+    let cipher_s2c = both(3)?;
+    let mac = |i: usize, cipher: &str| {
+        if is_aead(cipher) {
+            Some(IMPLICIT_MAC.to_string())
+        } else {
+            both(i)
+        }
+    };
+    Some(NegotiatedAlgorithms {
+        kex: both(0)?,
+        host_key: both(1)?,
+        mac_client_to_server: mac(4, &cipher_c2s)?,
+        mac_server_to_client: mac(5, &cipher_s2c)?,
+        cipher_client_to_server: cipher_c2s,
+        cipher_server_to_client: cipher_s2c,
+        compression_client_to_server: both(6)?,
+        compression_server_to_client: both(7)?,
+    })
+}
+
+#[cfg(test)]
+pub(crate) fn kexinit_payload(lists: [&str; 10]) -> Vec<u8> {
+    let mut payload = vec![MSG_KEXINIT];
+    payload.extend_from_slice(&[0u8; 16]);
+    for list in lists {
+        payload.extend_from_slice(&(list.len() as u32).to_be_bytes());
+        payload.extend_from_slice(list.as_bytes());
+    }
+    payload.push(0);
+    payload.extend_from_slice(&[0u8; 4]);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_exclude_weak_algorithms() {
+        let modern = CryptoProfile::Modern.preferences();
+        let compatible = CryptoProfile::Compatible.preferences();
+        for prefs in [&modern, &compatible] {
+            assert!(!prefs.host_key.contains(&"ssh-rsa".to_string()));
+            assert!(!prefs.cipher.iter().any(|c| c.ends_with("-cbc")));
+            assert!(!prefs.kex.iter().any(|k| k.ends_with("-sha1")));
+            assert!(!prefs.mac.iter().any(|m| m.contains("sha1")));
+        }
+        assert_eq!(modern.kex[0], "curve25519-sha256");
+        assert_eq!(modern.cipher[0], "chacha20-poly1305@openssh.com");
+
+        let legacy = CryptoProfile::Legacy.preferences();
+        assert!(legacy.host_key.contains(&"ssh-rsa".to_string()));
+        assert!(legacy.cipher.contains(&"aes128-cbc".to_string()));
+        assert_eq!(
+            CryptoProfile::from_name("Legacy"),
+            Some(CryptoProfile::Legacy)
+        );
+        assert_eq!(CryptoProfile::from_name("fips"), None);
+
+        for profile in [
+            CryptoProfile::Modern,
+            CryptoProfile::Compatible,
+            CryptoProfile::Legacy,
+        ] {
+            let preferred = profile.preferences().to_preferred().unwrap();
+            assert!(preferred
+                .kex
+                .contains(&kex::EXTENSION_OPENSSH_STRICT_KEX_AS_CLIENT));
+        }
+    }
+
+    #[test]
+    fn test_compression_order_in_preferred() {
+        let enabled = AlgorithmPreferences::default()
+            .with_compression(true)
+            .to_preferred()
+            .unwrap();
+        assert_eq!(
+            enabled.compression.as_ref(),
+            [
+                compression::ZLIB_LEGACY,
+                compression::ZLIB,
+                compression::NONE
+            ]
+        );
+
+        let disabled = AlgorithmPreferences::default()
+            .with_compression(false)
+            .to_preferred()
+            .unwrap();
+        assert_eq!(disabled.compression.as_ref(), [compression::NONE]);
+    }
+
+    #[test]
+    fn test_unknown_algorithm_is_rejected() {
+        let prefs = AlgorithmPreferences {
+            cipher: vec!["rot13".to_string()],
+            ..AlgorithmPreferences::default()
+        };
+        let err = prefs.to_preferred().unwrap_err();
+        assert_eq!(err.code, SshErrorCode::BadRequest);
+        assert_eq!(err.message, "Unsupported cipher algorithm: rot13");
+    }
+
+    #[test]
+    fn test_negotiation_follows_client_order() {
+        let client = KexInit::parse(&kexinit_payload([
+            "curve25519-sha256,ext-info-c,kex-strict-c-v00@openssh.com",
+            "ssh-ed25519,rsa-sha2-256",
+            "chacha20-poly1305@openssh.com,aes256-ctr",
+            "aes256-ctr",
+            "hmac-sha2-256-etm@openssh.com",
+            "hmac-sha2-256-etm@openssh.com",
+            "none",
+            "zlib@openssh.com,none",
+            "",
+            "",
+        ]))
+        .unwrap();
+        let server = KexInit::parse(&kexinit_payload([
+            "diffie-hellman-group14-sha256,curve25519-sha256,kex-strict-s-v00@openssh.com",
+            "rsa-sha2-256,ssh-ed25519",
+            "aes256-ctr,chacha20-poly1305@openssh.com",
+            "aes256-ctr",
+            "hmac-sha2-256-etm@openssh.com",
+            "hmac-sha2-256-etm@openssh.com",
+            "none,zlib@openssh.com",
+            "none,zlib@openssh.com",
+            "",
+            "",
+        ]))
+        .unwrap();
+
+        let negotiated = negotiate(&client, &server).unwrap();
+        assert_eq!(negotiated.kex, "curve25519-sha256");
+        assert_eq!(negotiated.host_key, "ssh-ed25519");
+        assert_eq!(
+            negotiated.cipher_client_to_server,
+            "chacha20-poly1305@openssh.com"
+        );
+        assert_eq!(negotiated.mac_client_to_server, IMPLICIT_MAC);
+        assert_eq!(negotiated.cipher_server_to_client, "aes256-ctr");
+        assert_eq!(
+            negotiated.mac_server_to_client,
+            "hmac-sha2-256-etm@openssh.com"
+        );
+        assert_eq!(negotiated.compression_server_to_client, "zlib@openssh.com");
+        assert!(KexInit::parse(&[21]).is_none());
+    }
+}
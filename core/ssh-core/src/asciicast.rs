@@ -0,0 +1,681 @@
+//! Запись сессии в формате asciicast v2 и воспроизведение записи как потока событий.
+//!
+//! Запись только явная: [`Recorder`] создаёт приложение и само передаёт ему события, ввод и
+//! изменения размера. Ввод по умолчанию не пишется вовсе (пароли на приглашениях `sudo`
+//! проходят через stdin), для обзора действий есть маскирование.
+
+use crate::events::EventHub;
+use crate::scrollback::append;
+use crate::{Delivery, EventStream, Pty, SequencedEvent, SshEvent, SshState, SubscribeOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{sleep_until, Instant};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Очередь вывода воспроизведения: плеер ждёт, пока подписчик не разберёт больше этого объёма
+const PLAYBACK_QUEUE_BYTES: usize = 64 * 1024;
+/// Предел времени события и момента воспроизведения, в секундах (около 31 года)
+const MAX_EVENT_TIME: f64 = 1e9;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsciicastError {
+    pub path: Option<PathBuf>,
+    /// Номер строки файла с 1; 0 — ошибка чтения, не связанная со строкой
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsciicastError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            path: None,
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for AsciicastError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}:{}: {}", path.display(), self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for AsciicastError {}
+
+/// Первая строка файла
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AsciicastHeader {
+    pub version: u8,
+    pub width: u16,
+    pub height: u16,
+    /// Начало записи, секунды Unix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Паузы длиннее этого числа секунд плеер сокращает до него
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl AsciicastHeader {
+    /// Размер и `TERM` из запрошенного PTY
+    pub fn for_pty(pty: &Pty) -> Self {
+        Self {
+            version: 2,
+            width: pty.cols,
+            height: pty.rows,
+            timestamp: None,
+            idle_time_limit: None,
+            title: None,
+            env: BTreeMap::from([("TERM".to_string(), pty.term.clone())]),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventCode {
+    /// `o`
+    Output,
+    /// `i`
+    Input,
+    /// `r`, данные — `{cols}x{rows}`
+    Resize,
+    /// `m`
+    Marker,
+    Other(String),
+}
+
+impl EventCode {
+    fn from_code(code: &str) -> Self {
+        match code {
+            "o" => EventCode::Output,
+            "i" => EventCode::Input,
+            "r" => EventCode::Resize,
+            "m" => EventCode::Marker,
+            other => EventCode::Other(other.to_string()),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            EventCode::Output => "o",
+            EventCode::Input => "i",
+            EventCode::Resize => "r",
+            EventCode::Marker => "m",
+            EventCode::Other(code) => code,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsciicastEvent {
+    /// Секунды от начала записи
+    pub time: f64,
+    pub code: EventCode,
+    pub data: String,
+}
+
+impl AsciicastEvent {
+    /// Размер из события `r`
+    pub fn size(&self) -> Option<(u16, u16)> {
+        if self.code != EventCode::Resize {
+            return None;
+        }
+        let (cols, rows) = self.data.split_once('x')?;
+        Some((cols.parse().ok()?, rows.parse().ok()?))
+    }
+}
+
+/// Что делать с вводом пользователя
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputCapture {
+    /// Ввод не записывается
+    #[default]
+    Off,
+    /// Печатные символы заменяются на `*`, управляющие (Enter, Ctrl-C, стрелки) сохраняются
+    Redacted,
+    /// Ввод как есть, кроме переданного через [`Recorder::record_secret_input`]
+    Full,
+}
+
+/// Пишет asciicast v2 построчно; время событий вывода — время их публикации в сессии
+pub struct Recorder<W: Write> {
+    writer: W,
+    started: SystemTime,
+    input: InputCapture,
+    last_time: f64,
+    /// Незавершённая UTF-8 последовательность в конце прошлого блока вывода
+    pending: Zeroizing<Vec<u8>>,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Записывает заголовок; без `timestamp` в нём ставится текущее время
+    pub fn new(
+        mut writer: W,
+        mut header: AsciicastHeader,
+        input: InputCapture,
+    ) -> io::Result<Self> {
+        let started = SystemTime::now();
+        header.version = 2;
+        if header.timestamp.is_none() {
+            header.timestamp = started.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+        }
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        Ok(Self {
+            writer,
+            started,
+            input,
+            last_time: 0.0,
+            pending: Zeroizing::new(Vec::new()),
+        })
+    }
+
+    /// `Stdout`/`Stderr` становятся событиями `o`, остальные события пропускаются
+    pub fn record_event(&mut self, event: &SequencedEvent) -> io::Result<()> {
+        let (SshEvent::Stdout { data } | SshEvent::Stderr { data }) = &event.event else {
+            return Ok(());
+        };
+        append(&mut self.pending, data);
+        let text = take_utf8(&mut self.pending);
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.write_event(event.timestamp, &EventCode::Output, &text)
+    }
+
+    /// Пишет события до закрытия сессии или конца потока. Если подписка отстала, запись
+    /// продолжается, а на месте пропуска ставится метка `m` с числом потерянных событий
+    pub async fn record_until_closed(
+        &mut self,
+        events: &mut EventStream<SequencedEvent>,
+    ) -> io::Result<()> {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    self.record_event(&event)?;
+                    if event.event
+                        == (SshEvent::Status {
+                            state: SshState::Closed,
+                        })
+                    {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    // Начало символа до пропуска не склеивается с выводом после него
+                    self.flush_pending()?;
+                    self.record_marker(&format!("{skipped} events lost"))?;
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+        self.writer.flush()
+    }
+
+    pub fn record_resize(&mut self, cols: u16, rows: u16) -> io::Result<()> {
+        self.write_event(
+            SystemTime::now(),
+            &EventCode::Resize,
+            &format!("{cols}x{rows}"),
+        )
+    }
+
+    /// Байты, переданные в `write_stdin`, с учётом [`InputCapture`]
+    pub fn record_input(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.input {
+            InputCapture::Off => Ok(()),
+            InputCapture::Redacted => self.write_input(&redact(data)),
+            InputCapture::Full => self.write_input(&String::from_utf8_lossy(data)),
+        }
+    }
+
+    /// Пароль или другой секрет, набранный в терминале: маскируется даже при `InputCapture::Full`
+    pub fn record_secret_input(&mut self, data: &[u8]) -> io::Result<()> {
+        match self.input {
+            InputCapture::Off => Ok(()),
+            InputCapture::Redacted | InputCapture::Full => self.write_input(&redact(data)),
+        }
+    }
+
+    /// Метка для навигации по записи
+    pub fn record_marker(&mut self, label: &str) -> io::Result<()> {
+        self.write_event(SystemTime::now(), &EventCode::Marker, label)
+    }
+
+    /// Дописывает остаток вывода и возвращает writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_pending()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Незавершённая последовательность UTF-8 пишется как есть, с заменой на `U+FFFD`
+    fn flush_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut text = take_utf8(&mut self.pending);
+        text.push(char::REPLACEMENT_CHARACTER);
+        self.pending.zeroize();
+        self.write_event(SystemTime::now(), &EventCode::Output, &text)
+    }
+
+    fn write_input(&mut self, text: &str) -> io::Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.write_event(SystemTime::now(), &EventCode::Input, text)
+    }
+
+    /// Время не убывает: снимок подписки может быть помечен раньше уже записанных событий
+    fn write_event(&mut self, at: SystemTime, code: &EventCode, data: &str) -> io::Result<()> {
+        let elapsed = at
+            .duration_since(self.started)
+            .unwrap_or_default()
+            .as_secs_f64();
+        self.last_time = self.last_time.max((elapsed * 1e6).round() / 1e6);
+        serde_json::to_writer(&mut self.writer, &(self.last_time, code.as_str(), data))?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+/// Готовый текст из начала буфера; неполная последовательность в конце остаётся в нём.
+/// Места хватает и на замену каждого байта `U+FFFD`, поэтому текст не перевыделяется
+fn take_utf8(pending: &mut Vec<u8>) -> Zeroizing<String> {
+    let mut text = Zeroizing::new(String::with_capacity(pending.len() * 3 + 3));
+    let mut rest = pending.as_slice();
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                rest = &[];
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).expect("validated prefix"));
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    None => {
+                        rest = after;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    let consumed = pending.len() - rest.len();
+    pending[..consumed].zeroize();
+    pending.drain(..consumed);
+    text
+}
+
+fn redact(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .chars()
+        .map(|c| if c.is_control() { c } else { '*' })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaybackOptions {
+    /// Множитель скорости: 2.0 — вдвое быстрее записи
+    pub speed: f64,
+    /// Предел пауз; без него берётся `idle_time_limit` из заголовка
+    pub max_idle: Option<Duration>,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            max_idle: None,
+        }
+    }
+}
+
+/// Разобранная запись
+#[derive(Clone, Debug, PartialEq)]
+pub struct Asciicast {
+    pub header: AsciicastHeader,
+    pub events: Vec<AsciicastEvent>,
+}
+
+impl Asciicast {
+    pub fn parse(reader: impl BufRead) -> Result<Self, AsciicastError> {
+        let mut lines = reader.lines().enumerate();
+        let header_line = match lines.next() {
+            Some((_, line)) => line.map_err(|e| AsciicastError::new(1, e.to_string()))?,
+            None => return Err(AsciicastError::new(1, "empty recording")),
+        };
+        let header: AsciicastHeader = serde_json::from_str(&header_line)
+            .map_err(|e| AsciicastError::new(1, format!("invalid header: {e}")))?;
+        if header.version != 2 {
+            return Err(AsciicastError::new(
+                1,
+                format!("unsupported asciicast version {}", header.version),
+            ));
+        }
+
+        let mut events = Vec::new();
+        for (index, line) in lines {
+            let number = index + 1;
+            let line = line.map_err(|e| AsciicastError::new(number, e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, code, data): (f64, String, String) = serde_json::from_str(&line)
+                .map_err(|e| AsciicastError::new(number, format!("invalid event: {e}")))?;
+            if !(0.0..=MAX_EVENT_TIME).contains(&time) {
+                return Err(AsciicastError::new(number, "invalid event time"));
+            }
+            events.push(AsciicastEvent {
+                time,
+                code: EventCode::from_code(&code),
+                data,
+            });
+        }
+        Ok(Self { header, events })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AsciicastError> {
+        let path = path.as_ref();
+        let with_path = |mut e: AsciicastError| {
+            e.path = Some(path.to_path_buf());
+            e
+        };
+        let file = std::fs::File::open(path)
+            .map_err(|e| with_path(AsciicastError::new(0, e.to_string())))?;
+        Self::parse(io::BufReader::new(file)).map_err(with_path)
+    }
+
+    /// Время вывода каждого события с учётом скорости и предела пауз
+    fn schedule(&self, options: PlaybackOptions) -> Vec<Duration> {
+        let max_idle = options
+            .max_idle
+            .map(|d| d.as_secs_f64())
+            .or(self.header.idle_time_limit)
+            .filter(|limit| *limit > 0.0);
+        let speed = if options.speed > 0.0 {
+            options.speed
+        } else {
+            1.0
+        };
+        let (mut previous, mut at) = (0.0, 0.0);
+        self.events
+            .iter()
+            .map(|event| {
+                let gap = (event.time - previous).max(0.0);
+                previous = event.time;
+                at += max_idle.map_or(gap, |limit| gap.min(limit));
+                // Крошечная скорость или сумма пауз не должны ронять задачу воспроизведения
+                let limit = Duration::from_secs_f64(MAX_EVENT_TIME);
+                Duration::try_from_secs_f64(at / speed).map_or(limit, |d| d.min(limit))
+            })
+            .collect()
+    }
+
+    /// Воспроизводит вывод в темпе записи как сессию: `Connecting`, `Ready`, `Stdout` по
+    /// событиям `o`, затем `Closing` и `Closed`. Ввод и изменения размера в поток не попадают —
+    /// они доступны в `events`. Доставка без потерь; сброс потока останавливает воспроизведение
+    pub fn play(self, options: PlaybackOptions) -> EventStream<SshEvent> {
+        let hub = Arc::new(EventHub::new());
+        let stream = crate::open_stream(
+            &hub,
+            SubscribeOptions {
+                delivery: Delivery::Lossless {
+                    capacity_bytes: PLAYBACK_QUEUE_BYTES,
+                },
+                scrollback: false,
+            },
+            None,
+            |sequenced| sequenced.event,
+        );
+        let schedule = self.schedule(options);
+        tokio::spawn(async move {
+            let _ = hub.transition(SshState::Connecting);
+            let _ = hub.transition(SshState::Ready);
+            let start = Instant::now();
+            for (event, at) in self.events.into_iter().zip(schedule) {
+                if event.code != EventCode::Output {
+                    continue;
+                }
+                if !hub.has_subscribers() {
+                    break;
+                }
+                sleep_until(start + at).await;
+                hub.send_data(SshEvent::Stdout {
+                    data: event.data.into_bytes().into(),
+                })
+                .await;
+            }
+            let _ = hub.transition(SshState::Closing);
+            let _ = hub.transition(SshState::Closed);
+            hub.finish();
+        });
+        stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_alloc::{leaked, with_secret};
+
+    fn at<W: Write>(recorder: &Recorder<W>, millis: u64, event: SshEvent) -> SequencedEvent {
+        SequencedEvent {
+            seq: 0,
+            timestamp: recorder.started + Duration::from_millis(millis),
+            event,
+        }
+    }
+
+    #[test]
+    fn test_recording_round_trips_with_redacted_input() {
+        let header = AsciicastHeader::for_pty(&Pty::default());
+        let mut recorder = Recorder::new(Vec::new(), header, InputCapture::Redacted).unwrap();
+        // `é` разрезан между двумя блоками вывода
+        let first = at(
+            &recorder,
+            500,
+            SshEvent::Stdout {
+                data: b"caf\xc3".to_vec().into(),
+            },
+        );
+        let second = at(
+            &recorder,
+            1250,
+            SshEvent::Stderr {
+                data: b"\xa9\r\n".to_vec().into(),
+            },
+        );
+        recorder.record_event(&first).unwrap();
+        recorder.record_event(&second).unwrap();
+        recorder.record_secret_input(b"hunter2\r").unwrap();
+        recorder.record_resize(100, 30).unwrap();
+        let bytes = recorder.finish().unwrap();
+
+        let cast = Asciicast::parse(bytes.as_slice()).unwrap();
+        assert_eq!((cast.header.width, cast.header.height), (80, 24));
+        assert_eq!(cast.header.env["TERM"], "xterm-256color");
+        assert!(cast.header.timestamp.is_some());
+        assert_eq!(
+            cast.events[..2],
+            [
+                AsciicastEvent {
+                    time: 0.5,
+                    code: EventCode::Output,
+                    data: "caf".to_string(),
+                },
+                AsciicastEvent {
+                    time: 1.25,
+                    code: EventCode::Output,
+                    data: "é\r\n".to_string(),
+                },
+            ]
+        );
+        assert_eq!(cast.events[2].code, EventCode::Input);
+        assert_eq!(cast.events[2].data, "*******\r");
+        assert_eq!(cast.events[3].size(), Some((100, 30)));
+        assert!(!String::from_utf8(bytes).unwrap().contains("hunter2"));
+
+        let mut off = Recorder::new(Vec::new(), cast.header, InputCapture::Off).unwrap();
+        off.record_input(b"ls\r").unwrap();
+        assert_eq!(
+            off.finish()
+                .unwrap()
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lagged_recording_marks_gap_and_continues() {
+        let hub = EventHub::new();
+        let mut events = crate::open_stream(&hub, SubscribeOptions::default(), None, |sequenced| {
+            sequenced
+        });
+        hub.transition(SshState::Connecting).unwrap();
+        hub.transition(SshState::Ready).unwrap();
+        for i in 0..300 {
+            hub.send_data(SshEvent::Stdout {
+                data: format!("{i},").into_bytes().into(),
+            })
+            .await;
+        }
+        hub.transition(SshState::Closing).unwrap();
+        hub.transition(SshState::Closed).unwrap();
+
+        let header = AsciicastHeader::for_pty(&Pty::default());
+        let mut recorder = Recorder::new(Vec::new(), header, InputCapture::Off).unwrap();
+        recorder.record_until_closed(&mut events).await.unwrap();
+        let cast = Asciicast::parse(recorder.finish().unwrap().as_slice()).unwrap();
+
+        let marker = cast
+            .events
+            .iter()
+            .position(|e| e.code == EventCode::Marker)
+            .expect("gap is marked");
+        assert!(cast.events[marker].data.ends_with(" events lost"));
+        assert_eq!(cast.events.last().unwrap().data, "299,");
+        assert!(cast.events[marker + 1..]
+            .iter()
+            .all(|e| e.code == EventCode::Output));
+    }
+
+    #[test]
+    fn test_parse_rejects_other_versions() {
+        let err =
+            Asciicast::parse(&b"{\"version\":1,\"width\":80,\"height\":24}\n"[..]).unwrap_err();
+        assert_eq!(err.line, 1);
+        let err =
+            Asciicast::parse(&b"{\"version\":2,\"width\":80,\"height\":24}\n\n[1.0, \"o\"]\n"[..])
+                .unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn test_recorder_leaves_no_output_copies() {
+        let mut out = Zeroizing::new(vec![0u8; 4096]);
+        let header = AsciicastHeader::for_pty(&Pty::default());
+        let mut recorder =
+            Recorder::new(io::Cursor::new(&mut out[..]), header, InputCapture::Off).unwrap();
+        // Хвост `€` без последнего байта остаётся в `pending` до следующего блока или `finish`
+        let event = at(
+            &recorder,
+            10,
+            SshEvent::Stdout {
+                data: with_secret(b"\xff", b"\xe2\x82"),
+            },
+        );
+        recorder.record_event(&event).unwrap();
+        drop(event);
+        let written = recorder.finish().unwrap().position() as usize;
+
+        let text = std::str::from_utf8(&out[..written]).unwrap();
+        assert!(text.contains("\"o\",\"\u{fffd}s3cr3t-7c1e9a\"]\n"));
+        assert!(text.ends_with("\"o\",\"\u{fffd}\"]\n"));
+        assert_eq!(leaked(), 0);
+    }
+
+    #[test]
+    fn test_parse_rejects_unbounded_event_time() {
+        let err = Asciicast::parse(
+            &b"{\"version\":2,\"width\":80,\"height\":24}\n[1e30,\"o\",\"x\"]\n"[..],
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_schedule_saturates_for_tiny_speed() {
+        let cast = Asciicast::parse(
+            &b"{\"version\":2,\"width\":80,\"height\":24}\n[0.0,\"o\",\"a\"]\n[1e9,\"o\",\"b\"]\n"
+                [..],
+        )
+        .unwrap();
+        let schedule = cast.schedule(PlaybackOptions {
+            speed: 1e-300,
+            max_idle: None,
+        });
+        assert_eq!(
+            schedule,
+            vec![Duration::ZERO, Duration::from_secs_f64(MAX_EVENT_TIME)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_playback_follows_recorded_timing() {
+        let cast = Asciicast::parse(
+            &br#"{"version":2,"width":80,"height":24,"idle_time_limit":2.0}
+[1.0,"o","a"]
+[1.5,"i","x"]
+[30.0,"o","b"]
+"#[..],
+        )
+        .unwrap();
+        let started = Instant::now();
+        let mut stream = cast.play(PlaybackOptions {
+            speed: 2.0,
+            max_idle: None,
+        });
+        let mut events = Vec::new();
+        while let Ok(event) = stream.recv().await {
+            let elapsed = started.elapsed().as_millis();
+            events.push((elapsed, event));
+        }
+        let output: Vec<_> = events
+            .iter()
+            .filter_map(|(t, e)| match e {
+                SshEvent::Stdout { data } => Some((*t, data.to_vec())),
+                _ => None,
+            })
+            .collect();
+        // Пауза 28.5 с сокращена до 2 с, всё вдвое быстрее
+        assert_eq!(output, vec![(500, b"a".to_vec()), (1750, b"b".to_vec())]);
+        assert_eq!(
+            events.last().unwrap().1,
+            SshEvent::Status {
+                state: SshState::Closed
+            }
+        );
+    }
+}
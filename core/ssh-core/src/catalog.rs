@@ -0,0 +1,772 @@
+//! Модель Host Catalog (SRS §5.1.1), импорт из `ssh_config` и экспорт обратно.
+
+use crate::ssh_config::{JumpHost, ResolvedHost, SshConfig, SshConfigError, StrictHostKeyChecking};
+use crate::{HostKeyPolicy, SessionOptions};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostAuth {
+    Password,
+    /// `key_id` ссылается на Key в vault; `identity_file` — исходный путь из `IdentityFile`
+    Key {
+        key_id: Option<Uuid>,
+        identity_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostProxy {
+    None,
+    Jump { jump_host_id: Uuid },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostOptions {
+    pub keep_alive_sec: Option<u32>,
+    pub timeout_sec: Option<u32>,
+    pub compression: Option<bool>,
+    pub strict_host_key_mode: Option<HostKeyPolicy>,
+}
+
+impl HostOptions {
+    /// `keepAliveSec`/`timeoutSec` в параметры подключения; незаданное берётся по умолчанию
+    pub fn session_options(&self) -> SessionOptions {
+        let defaults = SessionOptions::default();
+        SessionOptions {
+            timeout_ms: self
+                .timeout_sec
+                .map_or(defaults.timeout_ms, |sec| sec.saturating_mul(1000)),
+            keepalive_interval_ms: self
+                .keep_alive_sec
+                .filter(|&sec| sec > 0)
+                .map(|sec| sec.saturating_mul(1000)),
+            algorithms: defaults
+                .algorithms
+                .with_compression(self.compression.unwrap_or(false)),
+            ..defaults
+        }
+    }
+}
+
+/// Host (SRS §5.1.1) без полей синхронизации (`version`, `deleted`, временные метки)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Host {
+    pub id: Uuid,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub favorite: bool,
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    pub auth: HostAuth,
+    pub proxy: HostProxy,
+    pub options: HostOptions,
+}
+
+/// Директива `ssh_config`, которая не перенесена в Host
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedDirective {
+    pub keyword: String,
+    pub args: Vec<String>,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostImportReport {
+    pub host_id: Uuid,
+    pub title: String,
+    pub unsupported: Vec<UnsupportedDirective>,
+}
+
+/// Хост, который не удалось разрешить; остальные импортируются без него
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostImportFailure {
+    pub title: String,
+    pub error: SshConfigError,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportResult {
+    pub hosts: Vec<Host>,
+    /// Только хосты, у которых что-то не перенеслось
+    pub reports: Vec<HostImportReport>,
+    pub failures: Vec<HostImportFailure>,
+}
+
+impl ImportResult {
+    pub fn host(&self, title: &str) -> Option<&Host> {
+        self.hosts.iter().find(|h| h.title == title)
+    }
+
+    pub fn unsupported_for(&self, host_id: Uuid) -> &[UnsupportedDirective] {
+        self.reports
+            .iter()
+            .find(|r| r.host_id == host_id)
+            .map(|r| r.unsupported.as_slice())
+            .unwrap_or(&[])
+    }
+}
+
+/// `StrictHostKeyChecking` → политика; `no` не имеет эквивалента и сводится к accept-new
+pub fn host_key_policy(value: StrictHostKeyChecking) -> HostKeyPolicy {
+    match value {
+        StrictHostKeyChecking::Yes => HostKeyPolicy::Strict,
+        StrictHostKeyChecking::Ask => HostKeyPolicy::Ask,
+        StrictHostKeyChecking::AcceptNew | StrictHostKeyChecking::No => HostKeyPolicy::AcceptNew,
+    }
+}
+
+/// Импорт всех конкретных `Host`-блоков; хопы `ProxyJump` становятся отдельными Host.
+/// Ошибка разрешения одного хоста попадает в `failures` и не прерывает импорт остальных
+pub fn import_ssh_config(config: &SshConfig) -> ImportResult {
+    let mut importer = Importer {
+        config,
+        aliases: config.host_aliases(),
+        result: ImportResult::default(),
+        by_title: HashMap::new(),
+    };
+    for alias in importer.aliases.clone() {
+        // Ошибка уже записана в `failures`
+        let _ = importer.import_alias(&alias);
+    }
+    importer.result
+}
+
+struct Importer<'a> {
+    config: &'a SshConfig,
+    aliases: Vec<String>,
+    result: ImportResult,
+    by_title: HashMap<String, usize>,
+}
+
+impl Importer<'_> {
+    fn import_alias(&mut self, alias: &str) -> Result<Uuid, SshConfigError> {
+        if let Some(&idx) = self.by_title.get(alias) {
+            return Ok(self.result.hosts[idx].id);
+        }
+        let resolved = self.resolve(alias, alias)?;
+        self.import_resolved(alias.to_string(), resolved)
+    }
+
+    fn import_hop(&mut self, hop: &JumpHost) -> Result<Uuid, SshConfigError> {
+        if hop.user.is_none() && hop.port.is_none() && self.aliases.contains(&hop.host) {
+            return self.import_alias(&hop.host);
+        }
+
+        let title = hop.to_string();
+        if let Some(&idx) = self.by_title.get(&title) {
+            return Ok(self.result.hosts[idx].id);
+        }
+        // Как `ssh -J`: к хопу применяется его собственный конфиг, явные user/port важнее
+        let mut resolved = self.resolve(&title, &hop.host)?;
+        if let Some(user) = &hop.user {
+            resolved.user = user.clone();
+        }
+        if let Some(port) = hop.port {
+            resolved.port = port;
+        }
+        self.import_resolved(title, resolved)
+    }
+
+    fn import_resolved(
+        &mut self,
+        title: String,
+        resolved: ResolvedHost,
+    ) -> Result<Uuid, SshConfigError> {
+        let (host, unsupported) = convert(title.clone(), &resolved);
+        let id = host.id;
+        self.by_title.insert(title, self.result.hosts.len());
+        self.result.hosts.push(host);
+        for u in unsupported {
+            self.report(id, u);
+        }
+
+        if !resolved.proxy_jump.is_empty() {
+            match self.import_chain(&resolved.proxy_jump) {
+                Ok(jump_id) => self.link(id, jump_id),
+                Err(e) => self.report(
+                    id,
+                    UnsupportedDirective {
+                        keyword: "proxyjump".to_string(),
+                        args: resolved.proxy_jump.iter().map(|h| h.to_string()).collect(),
+                        reason: format!("jump host not imported: {e}"),
+                    },
+                ),
+            }
+        }
+        Ok(id)
+    }
+
+    /// Неудача записывается один раз, даже если хост встречается в нескольких цепочках
+    fn resolve(&mut self, title: &str, host: &str) -> Result<ResolvedHost, SshConfigError> {
+        self.config.resolve(host).inspect_err(|error| {
+            if !self.result.failures.iter().any(|f| f.title == title) {
+                self.result.failures.push(HostImportFailure {
+                    title: title.to_string(),
+                    error: error.clone(),
+                });
+            }
+        })
+    }
+
+    /// Возвращает последний хоп цепочки; каждый хоп прыгает через предыдущий
+    fn import_chain(&mut self, hops: &[JumpHost]) -> Result<Uuid, SshConfigError> {
+        let mut prev: Option<Uuid> = None;
+        for hop in hops {
+            let id = self.import_hop(hop)?;
+            if let Some(prev_id) = prev {
+                self.link(id, prev_id);
+            }
+            prev = Some(id);
+        }
+        Ok(prev.expect("ProxyJump chain is never empty"))
+    }
+
+    fn link(&mut self, id: Uuid, jump_id: Uuid) {
+        let idx = self.index(id);
+        let reason = match self.result.hosts[idx].proxy {
+            HostProxy::Jump { jump_host_id } if jump_host_id == jump_id => return,
+            HostProxy::Jump { .. } => "host already jumps through a different host",
+            HostProxy::None if self.reaches(jump_id, id) => "ProxyJump chain forms a cycle",
+            HostProxy::None => {
+                self.result.hosts[idx].proxy = HostProxy::Jump {
+                    jump_host_id: jump_id,
+                };
+                return;
+            }
+        };
+        let jump_title = self.result.hosts[self.index(jump_id)].title.clone();
+        self.report(
+            id,
+            UnsupportedDirective {
+                keyword: "proxyjump".to_string(),
+                args: vec![jump_title],
+                reason: reason.to_string(),
+            },
+        );
+    }
+
+    fn reaches(&self, from: Uuid, target: Uuid) -> bool {
+        let mut current = from;
+        for _ in 0..=self.result.hosts.len() {
+            if current == target {
+                return true;
+            }
+            match self.result.hosts[self.index(current)].proxy {
+                HostProxy::Jump { jump_host_id } => current = jump_host_id,
+                HostProxy::None => return false,
+            }
+        }
+        true
+    }
+
+    fn index(&self, id: Uuid) -> usize {
+        self.result
+            .hosts
+            .iter()
+            .position(|h| h.id == id)
+            .expect("imported host")
+    }
+
+    fn report(&mut self, host_id: Uuid, directive: UnsupportedDirective) {
+        if let Some(r) = self
+            .result
+            .reports
+            .iter_mut()
+            .find(|r| r.host_id == host_id)
+        {
+            r.unsupported.push(directive);
+            return;
+        }
+        let title = self.result.hosts[self.index(host_id)].title.clone();
+        self.result.reports.push(HostImportReport {
+            host_id,
+            title,
+            unsupported: vec![directive],
+        });
+    }
+}
+
+fn convert(title: String, resolved: &ResolvedHost) -> (Host, Vec<UnsupportedDirective>) {
+    let mut unsupported = Vec::new();
+    let unsupported_paths = |keyword: &str, paths: &[PathBuf], reason: &str| UnsupportedDirective {
+        keyword: keyword.to_string(),
+        args: paths.iter().map(|p| p.display().to_string()).collect(),
+        reason: reason.to_string(),
+    };
+
+    let auth = match resolved.identity_files.split_first() {
+        Some((first, rest)) => {
+            if !rest.is_empty() {
+                unsupported.push(unsupported_paths(
+                    "identityfile",
+                    rest,
+                    "only one key per host is supported",
+                ));
+            }
+            HostAuth::Key {
+                key_id: None,
+                identity_file: Some(first.clone()),
+            }
+        }
+        None => HostAuth::Password,
+    };
+
+    if let Some(cmd) = &resolved.proxy_command {
+        unsupported.push(UnsupportedDirective {
+            keyword: "proxycommand".to_string(),
+            args: vec![cmd.clone()],
+            reason: "only ProxyJump is supported".to_string(),
+        });
+    }
+    if !resolved.user_known_hosts_files.is_empty() {
+        unsupported.push(unsupported_paths(
+            "userknownhostsfile",
+            &resolved.user_known_hosts_files,
+            "known hosts are stored in the vault",
+        ));
+    }
+    if resolved.strict_host_key_checking == Some(StrictHostKeyChecking::No) {
+        unsupported.push(UnsupportedDirective {
+            keyword: "stricthostkeychecking".to_string(),
+            args: vec!["no".to_string()],
+            reason: "changed host keys are never accepted; imported as accept-new".to_string(),
+        });
+    }
+    // IdentitiesOnly уже подразумевается: каталог всегда использует ровно один ключ
+    unsupported.extend(
+        resolved
+            .other
+            .iter()
+            .filter(|o| o.keyword != "identitiesonly")
+            .map(|o| UnsupportedDirective {
+                keyword: o.keyword.clone(),
+                args: o.args.clone(),
+                reason: "not supported by the Host catalog".to_string(),
+            }),
+    );
+
+    let host = Host {
+        id: Uuid::new_v4(),
+        title,
+        tags: Vec::new(),
+        favorite: false,
+        hostname: resolved.hostname.clone(),
+        port: resolved.port,
+        username: resolved.user.clone(),
+        auth,
+        proxy: HostProxy::None,
+        options: HostOptions {
+            keep_alive_sec: resolved.server_alive_interval,
+            timeout_sec: resolved.connect_timeout,
+            compression: resolved.compression,
+            strict_host_key_mode: resolved.strict_host_key_checking.map(host_key_policy),
+        },
+    };
+    (host, unsupported)
+}
+
+/// Фрагмент `ssh_config` для `Include`: по блоку `Host` на каждый Host каталога
+pub fn export_ssh_config(hosts: &[Host]) -> String {
+    let aliases = export_aliases(hosts);
+    let alias_of = |id: Uuid| {
+        hosts
+            .iter()
+            .position(|h| h.id == id)
+            .map(|idx| aliases[idx].as_str())
+    };
+
+    let mut out = String::from("# Exported from the Host catalog\n");
+    for (host, alias) in hosts.iter().zip(&aliases) {
+        out.push('\n');
+        if *alias != host.title {
+            out.push_str(&format!("# {}\n", host.title.replace('\n', " ")));
+        }
+        out.push_str(&format!("Host {alias}\n"));
+        push_directive(&mut out, "HostName", &host.hostname.replace('%', "%%"));
+        push_directive(&mut out, "Port", &host.port.to_string());
+        push_directive(&mut out, "User", &host.username);
+
+        match &host.auth {
+            HostAuth::Password => {}
+            HostAuth::Key {
+                identity_file: Some(path),
+                ..
+            } => {
+                let path = path.display().to_string().replace('%', "%%");
+                push_directive(&mut out, "IdentityFile", &path);
+                push_directive(&mut out, "IdentitiesOnly", "yes");
+            }
+            HostAuth::Key {
+                key_id: Some(key_id),
+                identity_file: None,
+            } => out.push_str(&format!("  # key {key_id} has no identity file\n")),
+            HostAuth::Key { .. } => {}
+        }
+
+        if let HostProxy::Jump { jump_host_id } = host.proxy {
+            match alias_of(jump_host_id) {
+                Some(jump) => push_directive(&mut out, "ProxyJump", jump),
+                None => out.push_str(&format!("  # jump host {jump_host_id} is not exported\n")),
+            }
+        }
+
+        let options = &host.options;
+        if let Some(sec) = options.keep_alive_sec {
+            push_directive(&mut out, "ServerAliveInterval", &sec.to_string());
+        }
+        if let Some(sec) = options.timeout_sec {
+            push_directive(&mut out, "ConnectTimeout", &sec.to_string());
+        }
+        if let Some(compression) = options.compression {
+            push_directive(
+                &mut out,
+                "Compression",
+                if compression { "yes" } else { "no" },
+            );
+        }
+        if let Some(policy) = options.strict_host_key_mode {
+            let value = match policy {
+                HostKeyPolicy::Strict => "yes",
+                HostKeyPolicy::AcceptNew => "accept-new",
+                HostKeyPolicy::Ask => "ask",
+            };
+            push_directive(&mut out, "StrictHostKeyChecking", value);
+        }
+    }
+    out
+}
+
+/// В кавычках `"` и `\\` экранируются обратной косой чертой, как их читает `ssh`
+fn push_directive(out: &mut String, keyword: &str, value: &str) {
+    if value.is_empty()
+        || value.starts_with('#')
+        || value.contains(|c: char| c.is_whitespace() || c == '"')
+    {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        out.push_str(&format!("  {keyword} \"{escaped}\"\n"));
+    } else {
+        out.push_str(&format!("  {keyword} {value}\n"));
+    }
+}
+
+/// Заголовок Host → алиас без пробелов, шаблонов и `@`/`:` (иначе `ProxyJump` поймёт их как user/port)
+fn export_aliases(hosts: &[Host]) -> Vec<String> {
+    let mut used: Vec<String> = Vec::new();
+    hosts
+        .iter()
+        .map(|host| {
+            let base: String = host
+                .title
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                        c
+                    } else {
+                        '-'
+                    }
+                })
+                .collect();
+            let base = if base.is_empty() {
+                "host".to_string()
+            } else {
+                base
+            };
+            let mut alias = base.clone();
+            let mut n = 2;
+            while used.iter().any(|u| u.eq_ignore_ascii_case(&alias)) {
+                alias = format!("{base}-{n}");
+                n += 1;
+            }
+            used.push(alias.clone());
+            alias
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_config::ConfigContext;
+
+    fn import(text: &str) -> ImportResult {
+        let cfg = SshConfig::parse(text, ConfigContext::new("/home/alice", "alice")).unwrap();
+        import_ssh_config(&cfg)
+    }
+
+    fn jump_of(result: &ImportResult, title: &str) -> Option<String> {
+        match result.host(title)?.proxy {
+            HostProxy::Jump { jump_host_id } => result
+                .hosts
+                .iter()
+                .find(|h| h.id == jump_host_id)
+                .map(|h| h.title.clone()),
+            HostProxy::None => None,
+        }
+    }
+
+    #[test]
+    fn test_import_maps_host_fields() {
+        let result = import(
+            "Host web\n  HostName web.example.com\n  User deploy\n  Port 2222\n  \
+             IdentityFile ~/.ssh/id_ed25519\n  ServerAliveInterval 15\n  ConnectTimeout 10\n  \
+             Compression yes\n  StrictHostKeyChecking yes\n\
+             Host *.example.com\n  User wildcard\n",
+        );
+
+        assert_eq!(result.hosts.len(), 1);
+        let web = result.host("web").unwrap();
+        assert_eq!(web.hostname, "web.example.com");
+        assert_eq!(web.port, 2222);
+        assert_eq!(web.username, "deploy");
+        assert_eq!(
+            web.auth,
+            HostAuth::Key {
+                key_id: None,
+                identity_file: Some(PathBuf::from("/home/alice/.ssh/id_ed25519")),
+            }
+        );
+        assert_eq!(web.proxy, HostProxy::None);
+        assert_eq!(
+            web.options,
+            HostOptions {
+                keep_alive_sec: Some(15),
+                timeout_sec: Some(10),
+                compression: Some(true),
+                strict_host_key_mode: Some(HostKeyPolicy::Strict),
+            }
+        );
+        let options = web.options.session_options();
+        assert_eq!(options.keepalive_interval_ms, Some(15_000));
+        assert_eq!(
+            options.algorithms.compression,
+            ["zlib@openssh.com", "zlib", "none"]
+        );
+        assert!(result.reports.is_empty());
+    }
+
+    #[test]
+    fn test_strict_host_key_checking_mapping() {
+        assert_eq!(
+            host_key_policy(StrictHostKeyChecking::Yes),
+            HostKeyPolicy::Strict
+        );
+        assert_eq!(
+            host_key_policy(StrictHostKeyChecking::AcceptNew),
+            HostKeyPolicy::AcceptNew
+        );
+        assert_eq!(
+            host_key_policy(StrictHostKeyChecking::Ask),
+            HostKeyPolicy::Ask
+        );
+
+        let result = import("Host lab\n  StrictHostKeyChecking no\n");
+        let lab = result.host("lab").unwrap();
+        assert_eq!(
+            lab.options.strict_host_key_mode,
+            Some(HostKeyPolicy::AcceptNew)
+        );
+        assert_eq!(
+            result.unsupported_for(lab.id)[0].keyword,
+            "stricthostkeychecking"
+        );
+    }
+
+    #[test]
+    fn test_jump_chain_links_existing_and_synthesized_hosts() {
+        let result = import(
+            "Host bastion\n  HostName bastion.example.com\n  User jump\n\
+             Host db\n  HostName 10.0.0.7\n  ProxyJump bastion,ops@10.0.0.2:2200\n",
+        );
+
+        assert_eq!(result.hosts.len(), 3);
+        let hop = result.host("ops@10.0.0.2:2200").unwrap();
+        assert_eq!(hop.hostname, "10.0.0.2");
+        assert_eq!(hop.port, 2200);
+        assert_eq!(hop.username, "ops");
+        assert_eq!(jump_of(&result, "db").as_deref(), Some("ops@10.0.0.2:2200"));
+        assert_eq!(
+            jump_of(&result, "ops@10.0.0.2:2200").as_deref(),
+            Some("bastion")
+        );
+        assert_eq!(jump_of(&result, "bastion"), None);
+    }
+
+    #[test]
+    fn test_unsupported_directives_reported_per_host() {
+        let result = import(
+            "Host a\n  IdentityFile ~/.ssh/a1\n  IdentityFile ~/.ssh/a2\n  ForwardAgent yes\n  \
+             UserKnownHostsFile ~/.ssh/known_a\n\
+             Host b\n  ProxyCommand nc %h %p\n\
+             Host c\n  User clean\n",
+        );
+
+        let a = result.host("a").unwrap();
+        let keywords: Vec<&str> = result
+            .unsupported_for(a.id)
+            .iter()
+            .map(|u| u.keyword.as_str())
+            .collect();
+        assert_eq!(
+            keywords,
+            vec!["identityfile", "userknownhostsfile", "forwardagent"]
+        );
+        assert_eq!(
+            result.unsupported_for(a.id)[0].args,
+            vec!["/home/alice/.ssh/a2".to_string()]
+        );
+
+        let b = result.host("b").unwrap();
+        assert_eq!(result.unsupported_for(b.id)[0].args, vec!["nc b 22"]);
+
+        let c = result.host("c").unwrap();
+        assert!(result.unsupported_for(c.id).is_empty());
+        assert_eq!(result.reports.len(), 2);
+    }
+
+    #[test]
+    fn test_resolve_failure_skips_only_that_host() {
+        let result = import(
+            "Host broken\n  IdentityFile ~/.ssh/id_%x\n\
+             Host app\n  ProxyJump broken\n\
+             Host web\n  HostName web.example.com\n",
+        );
+
+        assert_eq!(result.failures.len(), 1);
+        assert_eq!(result.failures[0].title, "broken");
+        assert_eq!(result.failures[0].error.line, 2);
+        assert!(result.host("broken").is_none());
+        assert_eq!(result.host("web").unwrap().hostname, "web.example.com");
+
+        let app = result.host("app").unwrap();
+        assert_eq!(app.proxy, HostProxy::None);
+        let skipped = &result.unsupported_for(app.id)[0];
+        assert_eq!(
+            (skipped.keyword.as_str(), skipped.args.clone()),
+            ("proxyjump", vec!["broken".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_proxy_jump_cycle_is_reported() {
+        let result = import("Host a\n  ProxyJump b\nHost b\n  ProxyJump a\n");
+
+        assert_eq!(result.hosts.len(), 2);
+        let linked = [jump_of(&result, "a"), jump_of(&result, "b")];
+        assert_eq!(linked.iter().filter(|j| j.is_some()).count(), 1);
+        assert_eq!(result.reports.len(), 1);
+        assert_eq!(result.reports[0].unsupported[0].keyword, "proxyjump");
+    }
+
+    #[test]
+    fn test_export_writes_include_fragment() {
+        let bastion = Host {
+            id: Uuid::new_v4(),
+            title: "Bastion (prod)".to_string(),
+            tags: vec!["prod".to_string()],
+            favorite: true,
+            hostname: "bastion.example.com".to_string(),
+            port: 22,
+            username: "jump".to_string(),
+            auth: HostAuth::Password,
+            proxy: HostProxy::None,
+            options: HostOptions::default(),
+        };
+        let db = Host {
+            id: Uuid::new_v4(),
+            title: "db".to_string(),
+            tags: Vec::new(),
+            favorite: false,
+            hostname: "10.0.0.7".to_string(),
+            port: 2222,
+            username: "deploy".to_string(),
+            auth: HostAuth::Key {
+                key_id: None,
+                identity_file: Some(PathBuf::from("/home/alice/My Keys/db_ed25519")),
+            },
+            proxy: HostProxy::Jump {
+                jump_host_id: bastion.id,
+            },
+            options: HostOptions {
+                keep_alive_sec: Some(30),
+                timeout_sec: None,
+                compression: Some(false),
+                strict_host_key_mode: Some(HostKeyPolicy::AcceptNew),
+            },
+        };
+
+        let text = export_ssh_config(&[bastion, db]);
+        assert_eq!(
+            text,
+            "# Exported from the Host catalog\n\
+             \n\
+             # Bastion (prod)\n\
+             Host Bastion--prod-\n  HostName bastion.example.com\n  Port 22\n  User jump\n\
+             \n\
+             Host db\n  HostName 10.0.0.7\n  Port 2222\n  User deploy\n  \
+             IdentityFile \"/home/alice/My Keys/db_ed25519\"\n  IdentitiesOnly yes\n  \
+             ProxyJump Bastion--prod-\n  ServerAliveInterval 30\n  Compression no\n  \
+             StrictHostKeyChecking accept-new\n"
+        );
+    }
+
+    #[test]
+    fn test_export_round_trips_through_parser() {
+        let original = import(
+            "Host bastion\n  HostName bastion.example.com\n  User jump\n  \
+             StrictHostKeyChecking yes\n\
+             Host db\n  HostName 10.0.0.7\n  User deploy\n  Port 2222\n  \
+             IdentityFile ~/.ssh/db_%h\n  ProxyJump bastion,ops@10.0.0.2:2200\n  \
+             ServerAliveInterval 20\n  ConnectTimeout 5\n  Compression yes\n  \
+             StrictHostKeyChecking ask\n\
+             Host web\n  HostName web.example.com\n\
+             Host odd\n  User \"#o\\\"brien\"\n  \
+             IdentityFile \"~/keys/say \\\"hi\\\" \\\\ now\"\n",
+        );
+        let odd = original.host("odd").unwrap();
+        assert_eq!(odd.username, "#o\"brien");
+        assert_eq!(
+            odd.auth,
+            HostAuth::Key {
+                key_id: None,
+                identity_file: Some(PathBuf::from(r#"/home/alice/keys/say "hi" \ now"#)),
+            }
+        );
+
+        let exported = export_ssh_config(&original.hosts);
+        let reparsed =
+            SshConfig::parse(&exported, ConfigContext::new("/home/alice", "alice")).unwrap();
+        let round_trip = import_ssh_config(&reparsed);
+
+        assert!(round_trip.reports.is_empty());
+        assert_eq!(round_trip.hosts.len(), original.hosts.len());
+        let aliases = export_aliases(&original.hosts);
+        for (host, alias) in original.hosts.iter().zip(&aliases) {
+            let copy = round_trip.host(alias).unwrap();
+            assert_eq!(copy.hostname, host.hostname);
+            assert_eq!(copy.port, host.port);
+            assert_eq!(copy.username, host.username);
+            assert_eq!(copy.auth, host.auth);
+            assert_eq!(copy.options, host.options);
+
+            let jump_alias = |result: &ImportResult, h: &Host| match h.proxy {
+                HostProxy::Jump { jump_host_id } => result
+                    .hosts
+                    .iter()
+                    .position(|j| j.id == jump_host_id)
+                    .map(|idx| export_aliases(&result.hosts)[idx].clone()),
+                HostProxy::None => None,
+            };
+            assert_eq!(jump_alias(&round_trip, copy), jump_alias(&original, host));
+        }
+
+        // `ssh` видит ту же цепочку: db → ops@10.0.0.2:2200 → bastion
+        let db = reparsed.resolve("db").unwrap();
+        assert_eq!(db.proxy_jump[0].host, "ops-10.0.0.2-2200");
+        let hop = reparsed.resolve("ops-10.0.0.2-2200").unwrap();
+        assert_eq!((hop.hostname.as_str(), hop.port), ("10.0.0.2", 2200));
+        assert_eq!(hop.proxy_jump[0].host, "bastion");
+    }
+}
@@ -0,0 +1,134 @@
+//! Запросы канала с `want_reply` и переменные окружения для `env`.
+//!
+//! Сервер отвечает на запросы одного канала строго по порядку (RFC 4254 §5.4), поэтому
+//! `SSH_MSG_CHANNEL_SUCCESS`/`FAILURE` сопоставляются с очередью отправленных запросов.
+
+use crate::{SshErrorCode, SshEvent};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Переменные, которые OpenSSH по умолчанию отправляет через `SendEnv LANG LC_*`
+const LOCALE_PREFIXES: &[&str] = &["LC_"];
+const LOCALE_VARS: &[&str] = &["LANG", "LANGUAGE"];
+
+/// `LANG`, `LANGUAGE` и `LC_*` текущего процесса, отсортированные по имени
+pub fn locale_env() -> Vec<(String, String)> {
+    let mut env: Vec<_> = std::env::vars()
+        .filter(|(name, _)| {
+            LOCALE_VARS.contains(&name.as_str())
+                || LOCALE_PREFIXES.iter().any(|p| name.starts_with(p))
+        })
+        .collect();
+    env.sort();
+    env
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ChannelRequest {
+    Pty,
+    Env(String),
+    Shell,
+    Exec,
+}
+
+impl ChannelRequest {
+    /// Отказ в `env` — ожидаемая фильтрация `AcceptEnv`, остальное — ошибка без разрыва транспорта
+    fn rejected(self) -> SshEvent {
+        let what = match self {
+            ChannelRequest::Env(name) => return SshEvent::EnvRejected { name },
+            ChannelRequest::Pty => "PTY",
+            ChannelRequest::Shell => "Shell",
+            ChannelRequest::Exec => "Exec",
+        };
+        SshEvent::Error {
+            code: SshErrorCode::InternalError,
+            message: format!("{what} request rejected by server"),
+            retryable: false,
+            retry_after_ms: None,
+        }
+    }
+}
+
+/// Отправленные запросы, ждущие ответа; каналы — по номеру `ChannelId`
+#[derive(Default)]
+pub(crate) struct PendingRequests {
+    queue: Mutex<VecDeque<(u32, ChannelRequest)>>,
+}
+
+impl PendingRequests {
+    /// Регистрируется до отправки, иначе ответ может прийти раньше записи
+    pub(crate) fn push(&self, channel: u32, request: ChannelRequest) {
+        self.queue
+            .lock()
+            .expect("poisoned")
+            .push_back((channel, request));
+    }
+
+    /// Снимает старейший запрос канала; если ответа больше не ждут — `None`
+    pub(crate) fn complete(&self, channel: u32) -> Option<ChannelRequest> {
+        let mut queue = self.queue.lock().expect("poisoned");
+        let index = queue.iter().position(|(id, _)| *id == channel)?;
+        queue.remove(index).map(|(_, request)| request)
+    }
+
+    /// Событие об отказе сервера для старейшего запроса канала
+    pub(crate) fn rejected(&self, channel: u32) -> SshEvent {
+        match self.complete(channel) {
+            Some(request) => request.rejected(),
+            None => SshEvent::Error {
+                code: SshErrorCode::InternalError,
+                message: "Channel request rejected by server".to_string(),
+                retryable: false,
+                retry_after_ms: None,
+            },
+        }
+    }
+
+    /// Канал закрыт: ответов на его запросы уже не будет
+    pub(crate) fn forget(&self, channel: u32) {
+        self.queue
+            .lock()
+            .expect("poisoned")
+            .retain(|(id, _)| *id != channel);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.queue.lock().expect("poisoned").clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replies_match_requests_in_order_per_channel() {
+        let pending = PendingRequests::default();
+        let (a, b) = (1, 2);
+        pending.push(a, ChannelRequest::Env("LANG".to_string()));
+        pending.push(b, ChannelRequest::Exec);
+        pending.push(a, ChannelRequest::Env("LC_TIME".to_string()));
+        pending.push(a, ChannelRequest::Shell);
+
+        assert_eq!(
+            pending.complete(a),
+            Some(ChannelRequest::Env("LANG".to_string()))
+        );
+        assert_eq!(
+            pending.rejected(a),
+            SshEvent::EnvRejected {
+                name: "LC_TIME".to_string()
+            }
+        );
+        assert!(matches!(
+            pending.rejected(a),
+            SshEvent::Error { message, .. } if message == "Shell request rejected by server"
+        ));
+        assert_eq!(pending.complete(a), None);
+
+        pending.push(a, ChannelRequest::Pty);
+        pending.forget(a);
+        assert_eq!(pending.complete(a), None);
+        assert_eq!(pending.complete(b), Some(ChannelRequest::Exec));
+    }
+}
@@ -0,0 +1,193 @@
+//! TCP-подключение по RFC 8305 («Happy Eyeballs v2»): явное разрешение имени, чередование
+//! семейств адресов и попытки со сдвигом, каждая со своим таймаутом.
+
+use crate::errors::{self, Stage};
+use crate::{CauseKind, ErrorCause, SshError};
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, timeout, Duration, Instant};
+
+/// Какие адреса из DNS использовать и с какого семейства начинать
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// RFC 8305 §4: начиная с семейства первого адреса резолвера, порядок RFC 6724 внутри семейства
+    #[default]
+    Any,
+    PreferIpv4,
+    /// Оба семейства, IPv6 первым
+    PreferIpv6,
+    Ipv4Only,
+    Ipv6Only,
+}
+
+/// Параметры попыток подключения
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DialOptions {
+    pub family: AddressFamily,
+    /// Сдвиг между запусками попыток («Connection Attempt Delay»)
+    pub attempt_delay: Duration,
+    /// Таймаут одной попытки; общий предел задаёт `SessionOptions::timeout_ms`
+    pub attempt_timeout: Duration,
+}
+
+/// Порядок попыток: отфильтрованные по семейству адреса, чередуя семейства начиная с предпочтительного
+pub(crate) fn order_addresses(addrs: Vec<SocketAddr>, family: AddressFamily) -> Vec<SocketAddr> {
+    let first_is_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let (first, second) = match family {
+        AddressFamily::Any if !first_is_v6 => (v4, v6),
+        AddressFamily::Any | AddressFamily::PreferIpv6 => (v6, v4),
+        AddressFamily::PreferIpv4 => (v4, v6),
+        AddressFamily::Ipv4Only => (v4, Vec::new()),
+        AddressFamily::Ipv6Only => (v6, Vec::new()),
+    };
+    let mut ordered = Vec::with_capacity(first.len() + second.len());
+    let (mut first, mut second) = (first.into_iter(), second.into_iter());
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Первое установленное соединение; при неудаче — причины по каждому адресу и последняя ошибка
+pub(crate) async fn connect_tcp(
+    host: &str,
+    port: u16,
+    options: DialOptions,
+) -> Result<TcpStream, SshError> {
+    let resolved: Vec<_> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| errors::dns_failed(host, Some(&e)))?
+        .collect();
+    let mut pending = order_addresses(resolved, options.family)
+        .into_iter()
+        .peekable();
+    if pending.peek().is_none() {
+        return Err(errors::dns_failed(host, None));
+    }
+
+    let mut attempts = JoinSet::new();
+    let mut causes = Vec::new();
+    let mut last_error = errors::dns_failed(host, None);
+    let mut next_start = Instant::now();
+    loop {
+        // Следующий адрес стартует по таймеру или сразу, если все текущие попытки уже провалились
+        if pending.peek().is_some() && (attempts.is_empty() || Instant::now() >= next_start) {
+            let addr = pending.next().expect("peeked");
+            let attempt_timeout = options.attempt_timeout;
+            attempts.spawn(async move {
+                let result = match timeout(attempt_timeout, TcpStream::connect(addr)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut)),
+                };
+                (addr, result)
+            });
+            next_start = Instant::now() + options.attempt_delay;
+            continue;
+        }
+        if attempts.is_empty() {
+            break;
+        }
+        let joined = tokio::select! {
+            joined = attempts.join_next() => joined,
+            _ = sleep_until(next_start), if pending.peek().is_some() => continue,
+        };
+        match joined {
+            // Остальные попытки прерываются при сбросе `attempts`
+            Some(Ok((_, Ok(socket)))) => return Ok(socket),
+            Some(Ok((addr, Err(e)))) => {
+                causes.push(ErrorCause::new(
+                    CauseKind::Tcp,
+                    format!("{addr}: {}", e.kind()),
+                ));
+                last_error = errors::io_error(&e, Stage::Connect);
+            }
+            Some(Err(_)) | None => {}
+        }
+    }
+    // Сначала перечень адресов, затем причина последней неудачи
+    causes.append(&mut last_error.causes);
+    last_error.causes = causes;
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SshErrorCode;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_order_interleaves_families() {
+        let resolved = vec![
+            addr("192.0.2.3:22"),
+            addr("192.0.2.1:22"),
+            addr("[2001:db8::2]:22"),
+            addr("192.0.2.2:22"),
+            addr("[2001:db8::1]:22"),
+        ];
+        // `Any` начинает с семейства первого адреса и не переставляет адреса внутри семейства
+        assert_eq!(
+            order_addresses(resolved.clone(), AddressFamily::Any),
+            vec![
+                addr("192.0.2.3:22"),
+                addr("[2001:db8::2]:22"),
+                addr("192.0.2.1:22"),
+                addr("[2001:db8::1]:22"),
+                addr("192.0.2.2:22"),
+            ]
+        );
+        assert_eq!(
+            order_addresses(resolved[2..].to_vec(), AddressFamily::Any),
+            vec![
+                addr("[2001:db8::2]:22"),
+                addr("192.0.2.2:22"),
+                addr("[2001:db8::1]:22"),
+            ]
+        );
+        assert_eq!(
+            order_addresses(resolved.clone(), AddressFamily::PreferIpv6)[..2],
+            [addr("[2001:db8::2]:22"), addr("192.0.2.3:22")]
+        );
+        assert_eq!(
+            order_addresses(resolved.clone(), AddressFamily::PreferIpv4)[..2],
+            [addr("192.0.2.3:22"), addr("[2001:db8::2]:22")]
+        );
+        assert_eq!(
+            order_addresses(resolved, AddressFamily::Ipv6Only),
+            vec![addr("[2001:db8::2]:22"), addr("[2001:db8::1]:22")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_family_filter_without_addresses_is_dns_failure() {
+        let options = DialOptions {
+            family: AddressFamily::Ipv6Only,
+            attempt_delay: Duration::from_millis(250),
+            attempt_timeout: Duration::from_secs(1),
+        };
+        let err = connect_tcp("127.0.0.1", 22, options).await.unwrap_err();
+        assert_eq!(err.code, SshErrorCode::DnsFailed);
+    }
+
+    #[tokio::test]
+    async fn test_falls_through_to_listening_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let options = DialOptions {
+            family: AddressFamily::Any,
+            attempt_delay: Duration::from_millis(50),
+            attempt_timeout: Duration::from_secs(1),
+        };
+        // `localhost` может разрешиться и в ::1, где никто не слушает
+        let socket = connect_tcp("localhost", port, options).await.unwrap();
+        assert_eq!(socket.peer_addr().unwrap(), listener.local_addr().unwrap());
+    }
+}
@@ -0,0 +1,316 @@
+//! Сопоставление ошибок russh и ввода-вывода с `SshErrorCode` (SRS §12).
+//!
+//! Сообщения собираются из фиксированных фраз: текст ошибок russh, сервера и `Debug`
+//! внутренних структур в них не попадает, чтобы не утекли ключи, пароли или баннеры.
+
+use crate::{CauseKind, ErrorCause, SshError, SshErrorCode};
+use russh::client::{DisconnectReason, RemoteDisconnectInfo};
+use russh::{ChannelOpenFailure, Disconnect};
+use std::io;
+
+/// Этап, на котором произошла ошибка: от него зависит трактовка общих ошибок russh
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Connect,
+    Auth,
+    Channel,
+}
+
+impl Stage {
+    fn label(self) -> &'static str {
+        match self {
+            Stage::Connect => "connect",
+            Stage::Auth => "authentication",
+            Stage::Channel => "channel request",
+        }
+    }
+}
+
+/// `err` — ошибка резолвера; `None`, если имя разрешилось в пустой список адресов
+pub(crate) fn dns_failed(host: &str, err: Option<&io::Error>) -> SshError {
+    let detail = match err {
+        Some(e) => format!("lookup of {host} failed: {}", e.kind()),
+        None => format!("no addresses for {host}"),
+    };
+    SshError::new(
+        SshErrorCode::DnsFailed,
+        format!("Could not resolve host {host}"),
+        true,
+    )
+    .with_cause(ErrorCause::new(CauseKind::Dns, detail))
+}
+
+fn io_cause(err: &io::Error) -> ErrorCause {
+    let detail = match err.raw_os_error() {
+        Some(code) => format!("{} (os error {code})", err.kind()),
+        None => err.kind().to_string(),
+    };
+    ErrorCause::new(CauseKind::Io, detail)
+}
+
+pub(crate) fn io_error(err: &io::Error, stage: Stage) -> SshError {
+    use io::ErrorKind as K;
+
+    let (code, message, retryable) = match err.kind() {
+        K::ConnectionRefused => (SshErrorCode::ConnectFailed, "Connection refused", true),
+        K::NetworkUnreachable => (SshErrorCode::ConnectFailed, "Network unreachable", true),
+        K::HostUnreachable => (SshErrorCode::ConnectFailed, "Host unreachable", true),
+        K::AddrNotAvailable => (SshErrorCode::ConnectFailed, "Address not available", true),
+        K::NetworkDown => (SshErrorCode::ConnectFailed, "Network is down", true),
+        K::TimedOut => (SshErrorCode::Timeout, "Connection timed out", true),
+        K::ConnectionReset | K::ConnectionAborted | K::BrokenPipe | K::UnexpectedEof => {
+            (SshErrorCode::ConnectFailed, "Connection lost", true)
+        }
+        K::PermissionDenied => (
+            SshErrorCode::ConnectFailed,
+            "Connection not permitted",
+            false,
+        ),
+        _ => {
+            return SshError::new(
+                SshErrorCode::ConnectFailed,
+                format!("I/O error during {}", stage.label()),
+                true,
+            )
+            .with_cause(io_cause(err))
+        }
+    };
+    SshError::new(code, message, retryable).with_cause(io_cause(err))
+}
+
+pub(crate) fn russh_error(err: &russh::Error, stage: Stage) -> SshError {
+    use russh::Error as E;
+
+    let (code, message, retryable) = match err {
+        E::IO(e) => return io_error(e, stage),
+        E::Version => (
+            SshErrorCode::UnsupportedProtocol,
+            "SSH protocol version mismatch",
+            false,
+        ),
+        E::NoCommonAlgo { kind, ours, theirs } => {
+            // Имена алгоритмов публичны и нужны, чтобы подобрать профиль
+            return SshError::new(
+                SshErrorCode::UnsupportedProtocol,
+                format!("No common {} algorithm", algorithm_kind(kind)),
+                false,
+            )
+            .with_cause(ErrorCause::new(
+                CauseKind::Protocol,
+                format!("client offers: {}", ours.join(",")),
+            ))
+            .with_cause(ErrorCause::new(
+                CauseKind::Protocol,
+                format!("server offers: {}", theirs.join(",")),
+            ));
+        }
+        E::KexInit | E::Kex | E::UnknownAlgo | E::StrictKeyExchangeViolation { .. } => (
+            SshErrorCode::UnsupportedProtocol,
+            "Key exchange failed",
+            false,
+        ),
+        E::UnknownKey | E::WrongServerSig | E::KeyChanged { .. } => (
+            SshErrorCode::HostkeyRejected,
+            "Server host key rejected",
+            false,
+        ),
+        E::ConnectionTimeout | E::Elapsed(_) => {
+            (SshErrorCode::Timeout, "Connection timed out", true)
+        }
+        E::KeepaliveTimeout => (SshErrorCode::Timeout, "Keepalive timeout", true),
+        E::InactivityTimeout => (SshErrorCode::Timeout, "Inactivity timeout", true),
+        E::HUP | E::Disconnect | E::SendError => {
+            (SshErrorCode::ConnectFailed, "Connection lost", true)
+        }
+        E::PacketAuth | E::DecryptionError | E::PacketSize(_) => (
+            SshErrorCode::ConnectFailed,
+            "Corrupted packet received",
+            true,
+        ),
+        E::NotAuthenticated | E::NoAuthMethod => (
+            SshErrorCode::AuthFailed,
+            "No acceptable authentication method",
+            false,
+        ),
+        E::CouldNotReadKey | E::Keys(_) | E::SshKey(_) | E::Signature(_)
+            if stage == Stage::Auth =>
+        {
+            (SshErrorCode::AuthFailed, "Private key unusable", false)
+        }
+        E::ChannelOpenFailure(reason) => return channel_open_failure(*reason),
+        E::RequestDenied => (
+            SshErrorCode::InternalError,
+            "Request rejected by server",
+            false,
+        ),
+        _ => {
+            return SshError::new(
+                SshErrorCode::InternalError,
+                format!("Internal SSH error during {}", stage.label()),
+                false,
+            )
+        }
+    };
+    SshError::new(code, message, retryable)
+}
+
+pub(crate) fn channel_open_failure(reason: ChannelOpenFailure) -> SshError {
+    let (message, retryable) = match reason {
+        ChannelOpenFailure::AdministrativelyProhibited => {
+            ("Channel open prohibited by server", false)
+        }
+        ChannelOpenFailure::ConnectFailed => ("Channel open failed on server", true),
+        ChannelOpenFailure::UnknownChannelType => ("Channel type not supported by server", false),
+        ChannelOpenFailure::ResourceShortage => ("Server out of channel resources", true),
+        ChannelOpenFailure::Unknown => ("Channel open rejected by server", false),
+    };
+    SshError::new(SshErrorCode::InternalError, message, retryable)
+}
+
+/// Коды причин SSH_MSG_DISCONNECT (RFC 4253 §11.1)
+pub(crate) fn server_disconnect(info: &RemoteDisconnectInfo) -> SshError {
+    use SshErrorCode as C;
+
+    let (reason, code, message, retryable) = match info.reason_code {
+        Disconnect::HostNotAllowedToConnect => {
+            (1, C::ConnectFailed, "host not allowed to connect", false)
+        }
+        Disconnect::ProtocolError => (2, C::UnsupportedProtocol, "protocol error", false),
+        Disconnect::KeyExchangeFailed => (3, C::UnsupportedProtocol, "key exchange failed", false),
+        Disconnect::Reserved => (4, C::ConnectFailed, "reserved reason", true),
+        Disconnect::MACError => (5, C::ConnectFailed, "MAC error", true),
+        Disconnect::CompressionError => (6, C::UnsupportedProtocol, "compression error", false),
+        Disconnect::ServiceNotAvailable => {
+            (7, C::UnsupportedProtocol, "service not available", false)
+        }
+        Disconnect::ProtocolVersionNotSupported => (
+            8,
+            C::UnsupportedProtocol,
+            "protocol version not supported",
+            false,
+        ),
+        Disconnect::HostKeyNotVerifiable => {
+            (9, C::HostkeyRejected, "host key not verifiable", false)
+        }
+        Disconnect::ConnectionLost => (10, C::ConnectFailed, "connection lost", true),
+        Disconnect::ByApplication => (11, C::ConnectFailed, "closed by application", true),
+        Disconnect::TooManyConnections => (12, C::RateLimited, "too many connections", true),
+        Disconnect::AuthCancelledByUser => (13, C::AuthFailed, "authentication cancelled", false),
+        Disconnect::NoMoreAuthMethodsAvailable => {
+            (14, C::AuthFailed, "no more authentication methods", false)
+        }
+        Disconnect::IllegalUserName => (15, C::AuthFailed, "illegal user name", false),
+    };
+    SshError::new(code, format!("Server disconnected: {message}"), retryable).with_cause(
+        ErrorCause::new(CauseKind::ServerDisconnect, format!("reason code {reason}")),
+    )
+}
+
+/// Причина, по которой russh завершил цикл сессии без вызова со стороны приложения
+pub(crate) fn disconnect_reason(reason: &DisconnectReason<russh::Error>) -> SshError {
+    match reason {
+        DisconnectReason::ReceivedDisconnect(info) => server_disconnect(info),
+        DisconnectReason::Error(e) => russh_error(e, Stage::Connect),
+    }
+}
+
+fn algorithm_kind(kind: &russh::AlgorithmKind) -> &'static str {
+    match kind {
+        russh::AlgorithmKind::Kex => "key exchange",
+        russh::AlgorithmKind::Key => "host key",
+        russh::AlgorithmKind::Cipher => "cipher",
+        russh::AlgorithmKind::Compression => "compression",
+        russh::AlgorithmKind::Mac => "MAC",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disconnect(reason_code: Disconnect) -> SshError {
+        server_disconnect(&RemoteDisconnectInfo {
+            reason_code,
+            message: "password=hunter2".to_string(),
+            lang_tag: String::new(),
+        })
+    }
+
+    #[test]
+    fn test_io_errors_map_to_connect_codes() {
+        let refused = io_error(&io::ErrorKind::ConnectionRefused.into(), Stage::Connect);
+        assert_eq!(refused.code, SshErrorCode::ConnectFailed);
+        assert!(refused.retryable);
+
+        let unreachable = russh_error(
+            &russh::Error::IO(io::ErrorKind::NetworkUnreachable.into()),
+            Stage::Connect,
+        );
+        assert_eq!(unreachable.message, "Network unreachable");
+
+        let timeout = io_error(&io::ErrorKind::TimedOut.into(), Stage::Connect);
+        assert_eq!(timeout.code, SshErrorCode::Timeout);
+        assert_eq!(
+            timeout.retry_after_ms,
+            Some(crate::DEFAULT_TIMEOUT_RETRY_AFTER_MS)
+        );
+        assert_eq!(
+            timeout.causes,
+            vec![ErrorCause::new(CauseKind::Io, "timed out")]
+        );
+    }
+
+    #[test]
+    fn test_protocol_errors_are_not_retryable() {
+        let version = russh_error(&russh::Error::Version, Stage::Connect);
+        assert_eq!(version.code, SshErrorCode::UnsupportedProtocol);
+        assert!(!version.retryable);
+
+        let algo = russh_error(
+            &russh::Error::NoCommonAlgo {
+                kind: russh::AlgorithmKind::Cipher,
+                ours: vec!["aes256-gcm@openssh.com".into()],
+                theirs: vec!["3des-cbc".into()],
+            },
+            Stage::Connect,
+        );
+        assert_eq!(algo.code, SshErrorCode::UnsupportedProtocol);
+        assert_eq!(algo.message, "No common cipher algorithm");
+        assert_eq!(algo.causes[1].detail, "server offers: 3des-cbc");
+
+        let key = russh_error(&russh::Error::WrongServerSig, Stage::Connect);
+        assert_eq!(key.code, SshErrorCode::HostkeyRejected);
+    }
+
+    #[test]
+    fn test_auth_stage_and_disconnect_reasons() {
+        let auth = russh_error(&russh::Error::NoAuthMethod, Stage::Auth);
+        assert_eq!(auth.code, SshErrorCode::AuthFailed);
+        let key = russh_error(&russh::Error::CouldNotReadKey, Stage::Auth);
+        assert_eq!(key.code, SshErrorCode::AuthFailed);
+        let internal = russh_error(&russh::Error::CouldNotReadKey, Stage::Connect);
+        assert_eq!(internal.code, SshErrorCode::InternalError);
+
+        let busy = disconnect(Disconnect::TooManyConnections);
+        assert_eq!(busy.code, SshErrorCode::RateLimited);
+        assert!(busy.retryable);
+        assert_eq!(
+            busy.retry_after_ms,
+            Some(crate::DEFAULT_RATE_LIMIT_RETRY_AFTER_MS)
+        );
+        assert_eq!(
+            busy.causes,
+            vec![ErrorCause::new(
+                CauseKind::ServerDisconnect,
+                "reason code 12"
+            )]
+        );
+        let hostkey = disconnect(Disconnect::HostKeyNotVerifiable);
+        assert_eq!(hostkey.code, SshErrorCode::HostkeyRejected);
+        let version = disconnect(Disconnect::ProtocolVersionNotSupported);
+        assert_eq!(version.code, SshErrorCode::UnsupportedProtocol);
+        let auth = disconnect(Disconnect::NoMoreAuthMethodsAvailable);
+        assert_eq!(auth.code, SshErrorCode::AuthFailed);
+        assert!(!auth.message.contains("hunter2"));
+    }
+}
@@ -0,0 +1,548 @@
+//! Доставка событий сессии: broadcast (может отставать) и очереди подписчиков без потерь.
+
+use crate::flow::FlowControl;
+use crate::scrollback::{Scrollback, ScrollbackLimit};
+use crate::{Delivery, ReplayLimits, SequencedEvent, SshError, SshEvent, SshSession, SshState};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::SystemTime;
+use tokio::sync::{broadcast, mpsc, Notify, Semaphore};
+use tokio::task::JoinHandle;
+
+const BROADCAST_CAPACITY: usize = 256;
+
+/// Сколько пакетов канала ждёт доставки подписчикам, прежде чем обработчик russh остановится
+const PUMP_BUFFER: usize = 64;
+
+/// Очередь одного подписчика: отправитель вывода ждёт, пока в очереди больше `capacity` байт
+pub(crate) struct LosslessQueue {
+    events: Mutex<VecDeque<(SequencedEvent, u32)>>,
+    notify: Notify,
+    credits: Semaphore,
+    capacity: u32,
+    receiver_alive: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl LosslessQueue {
+    fn new(capacity: usize) -> Self {
+        let capacity = u32::try_from(capacity.max(1)).unwrap_or(u32::MAX);
+        Self {
+            events: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            credits: Semaphore::new(capacity as usize),
+            capacity,
+            receiver_alive: AtomicBool::new(true),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    fn push(&self, event: SequencedEvent, permits: u32) {
+        self.events
+            .lock()
+            .expect("poisoned")
+            .push_back((event, permits));
+        self.notify.notify_one();
+    }
+
+    fn permits_for(&self, bytes: usize) -> u32 {
+        u32::try_from(bytes).unwrap_or(u32::MAX).min(self.capacity)
+    }
+
+    /// Ждёт, пока подписчик освободит место под уже поставленное событие; после `release` не ждёт
+    async fn reserve(&self, permits: u32) {
+        if let Ok(permit) = self.credits.acquire_many(permits).await {
+            permit.forget();
+        }
+    }
+
+    pub(crate) fn pop(&self) -> Option<SequencedEvent> {
+        let (event, permits) = self.events.lock().expect("poisoned").pop_front()?;
+        if permits > 0 && !self.credits.is_closed() {
+            self.credits.add_permits(permits as usize);
+        }
+        Some(event)
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    pub(crate) async fn wait(&self) {
+        self.notify.notified().await;
+    }
+
+    fn release(&self) {
+        self.credits.close();
+    }
+
+    fn finish(&self) {
+        self.release();
+        self.finished.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+
+    /// Непрочитанный вывод затирается: подписчик его уже не заберёт
+    pub(crate) fn detach(&self) {
+        self.receiver_alive.store(false, Ordering::Release);
+        self.release();
+        self.events.lock().expect("poisoned").clear();
+    }
+
+    fn is_attached(&self) -> bool {
+        self.receiver_alive.load(Ordering::Acquire)
+    }
+}
+
+/// Буфер последних событий для возобновления подписки с `seq`
+struct Replay {
+    events: VecDeque<SequencedEvent>,
+    bytes: usize,
+    limits: ReplayLimits,
+}
+
+impl Replay {
+    fn push(&mut self, event: SequencedEvent) {
+        self.bytes += event_bytes(&event.event);
+        self.events.push_back(event);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.events.len() > self.limits.max_events || self.bytes > self.limits.max_bytes {
+            match self.events.pop_front() {
+                Some(old) => self.bytes -= event_bytes(&old.event),
+                None => break,
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.events.clear();
+        self.bytes = 0;
+    }
+}
+
+struct HubState {
+    session: SshState,
+    failure: Option<SshError>,
+    /// Номер подключения: сбои от транспорта прошлых попыток переподключения игнорируются
+    generation: u64,
+    supervised: bool,
+    last_seq: u64,
+    replay: Replay,
+    scrollback: Scrollback,
+    lossless: Vec<Arc<LosslessQueue>>,
+    /// Вывод, который ещё может лежать в кольце broadcast; старше ёмкости кольца уже вытеснен
+    ring_output: VecDeque<Weak<Mutex<Option<SequencedEvent>>>>,
+    finished: bool,
+}
+
+impl HubState {
+    /// Номер и время присваиваются под общей блокировкой: порядок `seq` совпадает с порядком доставки
+    fn stamp(&mut self, event: SshEvent) -> SequencedEvent {
+        self.last_seq += 1;
+        let event = SequencedEvent {
+            seq: self.last_seq,
+            timestamp: SystemTime::now(),
+            event,
+        };
+        self.replay.push(event.clone());
+        event
+    }
+
+    fn queues(&mut self) -> &[Arc<LosslessQueue>] {
+        self.lossless.retain(|q| q.is_attached());
+        &self.lossless
+    }
+
+    fn publish(&mut self, event: SshEvent, broadcast: &broadcast::Sender<RingEvent>) {
+        let event = self.stamp(event);
+        for queue in self.queues() {
+            queue.push(event.clone(), 0);
+        }
+        let _ = broadcast.send(RingEvent::new(event));
+    }
+}
+
+/// Элемент кольца broadcast. Отставший подписчик держит слот, пока не прочтёт его, поэтому
+/// событие лежит в общей ячейке, и вывод из неё hub стирает при `finish`
+#[derive(Clone)]
+pub(crate) struct RingEvent(Arc<Mutex<Option<SequencedEvent>>>);
+
+impl RingEvent {
+    fn new(event: SequencedEvent) -> Self {
+        Self(Arc::new(Mutex::new(Some(event))))
+    }
+
+    /// `None` — вывод, стёртый при закрытии сессии
+    pub(crate) fn get(&self) -> Option<SequencedEvent> {
+        self.0.lock().expect("poisoned").clone()
+    }
+}
+
+/// Результат подписки: события для snapshot и живой источник после них
+pub(crate) struct Subscription {
+    pub(crate) state: SshState,
+    pub(crate) last_seq: u64,
+    pub(crate) replay: Vec<SequencedEvent>,
+    pub(crate) scrollback: Vec<SshEvent>,
+    pub(crate) source: EventSource,
+}
+
+pub(crate) enum EventSource {
+    Broadcast(broadcast::Receiver<RingEvent>),
+    Lossless(Arc<LosslessQueue>),
+}
+
+/// Возобновление невозможно: нужные события уже вытеснены из буфера или ещё не существуют
+#[derive(Debug)]
+pub(crate) struct ReplayGap;
+
+/// Источник событий сессии, общий для `SshSession` и `ClientHandler`
+pub(crate) struct EventHub {
+    broadcast: broadcast::Sender<RingEvent>,
+    state: Mutex<HubState>,
+    flow: Mutex<Option<FlowControl>>,
+    failed: Notify,
+}
+
+impl EventHub {
+    pub(crate) fn new() -> Self {
+        Self {
+            broadcast: broadcast::Sender::new(BROADCAST_CAPACITY),
+            state: Mutex::new(HubState {
+                session: SshState::Init,
+                failure: None,
+                generation: 0,
+                supervised: false,
+                last_seq: 0,
+                replay: Replay {
+                    events: VecDeque::new(),
+                    bytes: 0,
+                    limits: ReplayLimits::default(),
+                },
+                scrollback: Scrollback::new(),
+                lossless: Vec::new(),
+                ring_output: VecDeque::new(),
+                finished: false,
+            }),
+            flow: Mutex::new(None),
+            failed: Notify::new(),
+        }
+    }
+
+    /// Управляющие события: не блокируют отправителя ни для одного подписчика
+    pub(crate) fn send(&self, event: SshEvent) {
+        self.state
+            .lock()
+            .expect("poisoned")
+            .publish(event, &self.broadcast);
+    }
+
+    pub(crate) fn session_state(&self) -> SshState {
+        self.state.lock().expect("poisoned").session
+    }
+
+    /// Асинхронный сбой, закрывший сессию, если он был
+    pub(crate) fn failure(&self) -> Option<SshError> {
+        self.state.lock().expect("poisoned").failure.clone()
+    }
+
+    /// Смена состояния и событие `Status` публикуются атомарно относительно подписки
+    pub(crate) fn transition(&self, to: SshState) -> Result<(), SshError> {
+        let mut state = self.state.lock().expect("poisoned");
+        if !SshSession::can_transition(state.session, to) {
+            return Err(SshError::invalid_state());
+        }
+        if state.session != to {
+            state.session = to;
+            state.publish(SshEvent::Status { state: to }, &self.broadcast);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.state.lock().expect("poisoned").generation
+    }
+
+    /// Асинхронный сбой (обрыв транспорта, таймаут, отключение сервером): `Error`, затем
+    /// `Closing` и `Closed`. Если сессия уже закрывается, сбой — следствие закрытия и не публикуется.
+    /// Под супервизором переподключения сессия останавливается на `Closing`, а решение о
+    /// закрытии принимает супервизор
+    pub(crate) fn fail(&self, error: &SshError, generation: u64) {
+        {
+            let mut state = self.state.lock().expect("poisoned");
+            if state.generation != generation
+                || matches!(state.session, SshState::Closing | SshState::Closed)
+            {
+                return;
+            }
+            state.failure = Some(error.clone());
+            state.publish(SshEvent::from(error), &self.broadcast);
+            state.session = SshState::Closing;
+            state.publish(
+                SshEvent::Status {
+                    state: SshState::Closing,
+                },
+                &self.broadcast,
+            );
+            if state.supervised {
+                self.failed.notify_one();
+                return;
+            }
+            state.session = SshState::Closed;
+            state.publish(
+                SshEvent::Status {
+                    state: SshState::Closed,
+                },
+                &self.broadcast,
+            );
+        }
+        self.release();
+        self.finish();
+    }
+
+    pub(crate) fn supervise(&self) {
+        self.state.lock().expect("poisoned").supervised = true;
+    }
+
+    pub(crate) fn take_failure(&self) -> Option<SshError> {
+        self.state.lock().expect("poisoned").failure.take()
+    }
+
+    /// Ждёт сбоя под супервизором и забирает его
+    pub(crate) async fn wait_failure(&self) -> SshError {
+        loop {
+            if let Some(error) = self.take_failure() {
+                return error;
+            }
+            self.failed.notified().await;
+        }
+    }
+
+    /// Новая попытка подключения: состояние снова `Connecting`, транспорт прошлой попытки
+    /// больше не может закрыть сессию
+    pub(crate) fn restart(&self) {
+        let mut state = self.state.lock().expect("poisoned");
+        state.generation += 1;
+        state.failure = None;
+        if state.session != SshState::Connecting {
+            state.session = SshState::Connecting;
+            state.publish(
+                SshEvent::Status {
+                    state: SshState::Connecting,
+                },
+                &self.broadcast,
+            );
+        }
+    }
+
+    /// `Stdout`/`Stderr`: сначала ждёт кредитов управления потоком, затем ставит событие
+    /// во все очереди сразу (порядок общий для всех) и ждёт, пока каждая lossless-очередь
+    /// не освободит место. Вызывается из [`ChannelPump`], а не из обработчика russh
+    pub(crate) async fn send_data(&self, event: SshEvent) {
+        let bytes = event_bytes(&event);
+        let flow = self.flow.lock().expect("poisoned").clone();
+        if let Some(flow) = flow {
+            flow.consume(bytes).await;
+        }
+
+        let reservations: Vec<_> = {
+            let mut state = self.state.lock().expect("poisoned");
+            // Хвост канала, дошедший после закрытия сессии, уже некому отдать
+            if state.finished {
+                return;
+            }
+            state.scrollback.push(&event);
+            let event = state.stamp(event);
+            let reservations = state
+                .queues()
+                .iter()
+                .map(|queue| {
+                    let permits = queue.permits_for(bytes);
+                    queue.push(event.clone(), permits);
+                    (Arc::clone(queue), permits)
+                })
+                .collect();
+            let ring = RingEvent::new(event);
+            if state.ring_output.len() == BROADCAST_CAPACITY {
+                state.ring_output.pop_front();
+            }
+            state.ring_output.push_back(Arc::downgrade(&ring.0));
+            let _ = self.broadcast.send(ring);
+            reservations
+        };
+
+        for (queue, permits) in reservations {
+            queue.reserve(permits).await;
+        }
+    }
+
+    /// Есть ли ещё читающие lossless-подписчики
+    pub(crate) fn has_subscribers(&self) -> bool {
+        !self.state.lock().expect("poisoned").queues().is_empty()
+    }
+
+    pub(crate) fn last_seq(&self) -> u64 {
+        self.state.lock().expect("poisoned").last_seq
+    }
+
+    pub(crate) fn set_scrollback(&self, limit: Option<ScrollbackLimit>) {
+        self.state
+            .lock()
+            .expect("poisoned")
+            .scrollback
+            .set_limit(limit);
+    }
+
+    pub(crate) fn set_replay_limits(&self, limits: ReplayLimits) {
+        let mut state = self.state.lock().expect("poisoned");
+        state.replay.limits = limits;
+        state.replay.trim();
+    }
+
+    /// Подписка атомарна относительно публикации: между replay и живым потоком нет разрыва
+    pub(crate) fn subscribe(
+        &self,
+        delivery: Delivery,
+        resume_after: Option<u64>,
+        with_scrollback: bool,
+    ) -> Result<Subscription, ReplayGap> {
+        let mut state = self.state.lock().expect("poisoned");
+
+        let replay = match resume_after {
+            None => Vec::new(),
+            Some(after) if after > state.last_seq => return Err(ReplayGap),
+            Some(after) => {
+                let oldest = state
+                    .replay
+                    .events
+                    .front()
+                    .map_or(state.last_seq + 1, |e| e.seq);
+                if after + 1 < oldest {
+                    return Err(ReplayGap);
+                }
+                state
+                    .replay
+                    .events
+                    .iter()
+                    .filter(|e| e.seq > after)
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        let source = match delivery {
+            Delivery::Lossy => EventSource::Broadcast(self.broadcast.subscribe()),
+            Delivery::Lossless { capacity_bytes } => {
+                let queue = Arc::new(LosslessQueue::new(capacity_bytes));
+                if state.finished {
+                    queue.finish();
+                } else {
+                    state.lossless.push(Arc::clone(&queue));
+                }
+                EventSource::Lossless(queue)
+            }
+        };
+
+        let scrollback = if with_scrollback {
+            state.scrollback.events()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Subscription {
+            state: state.session,
+            last_seq: state.last_seq,
+            replay,
+            scrollback,
+            source,
+        })
+    }
+
+    /// Включает (или снимает при `None`) кредитное управление потоком вывода
+    pub(crate) fn set_flow_control(&self, flow: Option<FlowControl>) {
+        let previous = std::mem::replace(&mut *self.flow.lock().expect("poisoned"), flow);
+        if let Some(previous) = previous {
+            previous.release();
+        }
+    }
+
+    pub(crate) fn flow_control(&self) -> Option<FlowControl> {
+        self.flow.lock().expect("poisoned").clone()
+    }
+
+    /// Снимает backpressure (отключение): ожидающие `send_data` завершаются
+    pub(crate) fn release(&self) {
+        if let Some(flow) = self.flow.lock().expect("poisoned").as_ref() {
+            flow.release();
+        }
+        for queue in self.state.lock().expect("poisoned").queues() {
+            queue.release();
+        }
+    }
+
+    /// Больше событий не будет: lossless-подписчики дочитывают очередь и получают `Closed`,
+    /// буферы replay и scrollback и вывод в кольце broadcast затираются
+    pub(crate) fn finish(&self) {
+        let mut state = self.state.lock().expect("poisoned");
+        state.finished = true;
+        state.replay.clear();
+        state.scrollback.clear();
+        for queue in state.lossless.drain(..) {
+            queue.finish();
+        }
+        // Отставший подписчик держал бы вывод в кольце до своего удаления
+        for cell in state.ring_output.drain(..) {
+            if let Some(cell) = cell.upgrade() {
+                cell.lock().expect("poisoned").take();
+            }
+        }
+    }
+}
+
+/// Доставка событий одного канала в отдельной задаче. Обработчик russh только кладёт их
+/// в ограниченный буфер, поэтому медленный подписчик не останавливает keepalive, ввод и другие
+/// каналы, пока буфер не заполнен; после этого ждёт весь цикл сессии, а с ним и они
+pub(crate) struct ChannelPump {
+    tx: mpsc::Sender<SshEvent>,
+    task: JoinHandle<()>,
+}
+
+impl ChannelPump {
+    pub(crate) fn spawn(events: Arc<EventHub>) -> Self {
+        let (tx, mut rx) = mpsc::channel(PUMP_BUFFER);
+        let task = tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                match event {
+                    SshEvent::Stdout { .. } | SshEvent::Stderr { .. } => {
+                        events.send_data(event).await
+                    }
+                    event => events.send(event),
+                }
+            }
+        });
+        Self { tx, task }
+    }
+
+    /// События канала публикуются в порядке `push`, вывод и управляющие вперемешку
+    pub(crate) async fn push(&self, event: SshEvent) {
+        // Задача завершается только после того, как закрыт отправитель
+        let _ = self.tx.send(event).await;
+    }
+
+    /// Дожидается доставки всего, что уже принято
+    pub(crate) async fn flush(self) {
+        drop(self.tx);
+        let _ = self.task.await;
+    }
+}
+
+fn event_bytes(event: &SshEvent) -> usize {
+    match event {
+        SshEvent::Stdout { data } | SshEvent::Stderr { data } => data.len(),
+        _ => 0,
+    }
+}
@@ -0,0 +1,348 @@
+//! Сценарии в духе `expect` поверх вывода PTY: ждать строку или регулярное выражение,
+//! отвечать и забирать текст между приглашениями.
+//!
+//! Работает с обычным [`EventStream`] сессии. Вывод сопоставляется как есть, вместе с
+//! управляющими последовательностями терминала; для устройств, которые раскрашивают
+//! приглашение, шаблон должен это учитывать.
+
+use crate::scrollback;
+use crate::{
+    Delivery, EventStream, SshError, SshErrorCode, SshEvent, SshSession, SshState, SubscribeOptions,
+};
+use regex::bytes::Regex;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{timeout_at, Duration, Instant};
+use zeroize::{Zeroize, Zeroizing};
+
+pub const DEFAULT_EXPECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Сколько непросмотренного вывода хранится; более старые байты отбрасываются
+pub const DEFAULT_MAX_BUFFER: usize = 1024 * 1024;
+
+/// Очередь подписки [`Expect::subscribe`]
+const EXPECT_QUEUE_BYTES: usize = 256 * 1024;
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Literal(String),
+    Regex(Regex),
+    /// Конец вывода: сессия закрыта
+    Eof,
+}
+
+impl Pattern {
+    pub fn literal(text: impl Into<String>) -> Self {
+        Pattern::Literal(text.into())
+    }
+
+    /// Некорректное выражение — `BadRequest`
+    pub fn regex(pattern: &str) -> Result<Self, SshError> {
+        Regex::new(pattern).map(Pattern::Regex).map_err(|e| {
+            SshError::new(
+                SshErrorCode::BadRequest,
+                format!("Invalid expect pattern: {e}"),
+                false,
+            )
+        })
+    }
+
+    /// Начало и конец первого совпадения
+    fn find(&self, buffer: &[u8]) -> Option<(usize, usize)> {
+        match self {
+            Pattern::Literal(text) if text.is_empty() => Some((0, 0)),
+            Pattern::Literal(text) => buffer
+                .windows(text.len())
+                .position(|w| w == text.as_bytes())
+                .map(|start| (start, start + text.len())),
+            Pattern::Regex(regex) => regex.find(buffer).map(|m| (m.start(), m.end())),
+            Pattern::Eof => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Pattern::Literal(text) => format!("{text:?}"),
+            Pattern::Regex(regex) => format!("/{}/", regex.as_str()),
+            Pattern::Eof => "EOF".to_string(),
+        }
+    }
+}
+
+/// Результат ожидания; текст — вывод как есть, невалидный UTF-8 заменён на `U+FFFD`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpectMatch {
+    /// Номер сработавшего шаблона в списке
+    pub index: usize,
+    /// Вывод от прошлого совпадения до этого
+    pub before: String,
+    pub matched: String,
+    /// Группы регулярного выражения, начиная с первой
+    pub captures: Vec<Option<String>>,
+}
+
+/// Ожидание шаблонов в выводе сессии
+pub struct Expect {
+    events: EventStream<SshEvent>,
+    buffer: Zeroizing<Vec<u8>>,
+    timeout: Duration,
+    max_buffer: usize,
+    eof: bool,
+}
+
+impl Expect {
+    /// Поток должен быть lossless: при `Lagged` часть вывода потеряна и ожидание завершается ошибкой
+    pub fn new(events: EventStream<SshEvent>) -> Self {
+        Self {
+            events,
+            buffer: Zeroizing::new(Vec::new()),
+            timeout: DEFAULT_EXPECT_TIMEOUT,
+            max_buffer: DEFAULT_MAX_BUFFER,
+            eof: false,
+        }
+    }
+
+    /// Lossless-подписка на сессию; вывод до подписки не виден, поэтому её делают до `open_pty`
+    pub fn subscribe(session: &SshSession) -> Self {
+        Self::new(session.subscribe_events_with(SubscribeOptions {
+            delivery: Delivery::Lossless {
+                capacity_bytes: EXPECT_QUEUE_BYTES,
+            },
+            scrollback: false,
+        }))
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn set_max_buffer(&mut self, bytes: usize) {
+        self.max_buffer = bytes.max(1);
+        self.trim();
+    }
+
+    /// Полученный, но ещё не совпавший вывод
+    pub fn buffer(&self) -> String {
+        lossy(&self.buffer)
+    }
+
+    pub async fn expect(&mut self, pattern: &Pattern) -> Result<ExpectMatch, SshError> {
+        self.expect_any(std::slice::from_ref(pattern)).await
+    }
+
+    /// Первое по положению в выводе совпадение любого из шаблонов; при равенстве — шаблон
+    /// с меньшим номером. Таймаут — `Timeout`, закрытие сессии без `Pattern::Eof` — `NotReady`
+    pub async fn expect_any(&mut self, patterns: &[Pattern]) -> Result<ExpectMatch, SshError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(found) = self.take_match(patterns) {
+                return Ok(found);
+            }
+            if self.eof {
+                return match patterns.iter().position(|p| matches!(p, Pattern::Eof)) {
+                    Some(index) => {
+                        let before = lossy(&self.buffer);
+                        self.buffer.zeroize();
+                        Ok(ExpectMatch {
+                            index,
+                            before,
+                            matched: String::new(),
+                            captures: Vec::new(),
+                        })
+                    }
+                    None => Err(SshError::new(
+                        SshErrorCode::NotReady,
+                        format!("Session closed while waiting for {}", describe(patterns)),
+                        false,
+                    )),
+                };
+            }
+            match timeout_at(deadline, self.events.recv()).await {
+                Err(_) => {
+                    return Err(SshError::new(
+                        SshErrorCode::Timeout,
+                        format!("Timed out waiting for {}", describe(patterns)),
+                        false,
+                    ))
+                }
+                Ok(Ok(SshEvent::Stdout { data } | SshEvent::Stderr { data })) => {
+                    scrollback::append(&mut self.buffer, &data);
+                    self.trim();
+                }
+                Ok(Ok(SshEvent::Status {
+                    state: SshState::Closed,
+                }))
+                | Ok(Err(RecvError::Closed)) => self.eof = true,
+                Ok(Ok(_)) => {}
+                Ok(Err(RecvError::Lagged(skipped))) => {
+                    return Err(SshError::new(
+                        SshErrorCode::BadRequest,
+                        format!("{skipped} events lost; expect needs a lossless subscription"),
+                        false,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Ждёт шаблон и отправляет ответ в stdin сессии
+    pub async fn respond(
+        &mut self,
+        session: &mut SshSession,
+        pattern: &Pattern,
+        response: &[u8],
+    ) -> Result<ExpectMatch, SshError> {
+        let found = self.expect(pattern).await?;
+        session.write_stdin(response).await?;
+        Ok(found)
+    }
+
+    fn take_match(&mut self, patterns: &[Pattern]) -> Option<ExpectMatch> {
+        let (index, (start, end)) = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| Some((i, p.find(&self.buffer)?)))
+            .min_by_key(|&(i, (start, _))| (start, i))?;
+        let captures = match &patterns[index] {
+            Pattern::Regex(regex) => regex
+                .captures(&self.buffer)
+                .map(|caps| {
+                    caps.iter()
+                        .skip(1)
+                        .map(|group| group.map(|g| lossy(g.as_bytes())))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let found = ExpectMatch {
+            index,
+            before: lossy(&self.buffer[..start]),
+            matched: lossy(&self.buffer[start..end]),
+            captures,
+        };
+        self.consume(end);
+        Some(found)
+    }
+
+    fn trim(&mut self) {
+        if self.buffer.len() > self.max_buffer {
+            self.consume(self.buffer.len() - self.max_buffer);
+        }
+    }
+
+    /// Просмотренный вывод затирается до сдвига, чтобы не остаться в хвосте буфера
+    fn consume(&mut self, len: usize) {
+        self.buffer[..len].zeroize();
+        self.buffer.drain(..len);
+    }
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn describe(patterns: &[Pattern]) -> String {
+    patterns
+        .iter()
+        .map(Pattern::describe)
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventHub;
+    use crate::test_alloc::{leaked, with_secret};
+    use std::sync::Arc;
+
+    fn scripted(hub: &Arc<EventHub>) -> Expect {
+        let events = crate::open_stream(
+            hub,
+            SubscribeOptions {
+                delivery: Delivery::Lossless {
+                    capacity_bytes: EXPECT_QUEUE_BYTES,
+                },
+                scrollback: false,
+            },
+            None,
+            |sequenced| sequenced.event,
+        );
+        Expect::new(events)
+    }
+
+    async fn output(hub: &EventHub, text: &str) {
+        hub.send_data(SshEvent::Stdout {
+            data: text.as_bytes().to_vec().into(),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_expect_captures_text_between_prompts() {
+        let hub = Arc::new(EventHub::new());
+        let mut exp = scripted(&hub);
+        output(&hub, "Username: ").await;
+        output(&hub, "\r\nswitch01# show ver").await;
+        output(&hub, "sion\r\nIOS 15.2(4)\r\nswitch01# ").await;
+
+        let found = exp.expect(&Pattern::literal("Username:")).await.unwrap();
+        assert_eq!(found.before, "");
+
+        let prompt = Pattern::regex(r"(\w+)# ").unwrap();
+        let found = exp.expect(&prompt).await.unwrap();
+        assert_eq!(found.captures, vec![Some("switch01".to_string())]);
+        let found = exp
+            .expect_any(&[Pattern::literal("% Invalid"), prompt])
+            .await
+            .unwrap();
+        assert_eq!(found.index, 1);
+        assert_eq!(found.before, "show version\r\nIOS 15.2(4)\r\n");
+        assert_eq!(exp.buffer(), "");
+    }
+
+    #[tokio::test]
+    async fn test_consumed_output_is_wiped() {
+        let hub = Arc::new(EventHub::new());
+        let mut exp = scripted(&hub);
+        hub.send_data(SshEvent::Stdout {
+            data: with_secret(b"api-key: ", b"\r\n"),
+        })
+        .await;
+        let found = exp.expect(&Pattern::literal("api-key: ")).await.unwrap();
+        assert_eq!(found.before, "");
+
+        // Буфер растёт, пока секрет ещё в нём, и сразу обрезается до 16 байт
+        exp.set_max_buffer(16);
+        output(&hub, "user@host:~$ ls").await;
+        let found = exp.expect(&Pattern::literal("$ ")).await.unwrap();
+        assert_eq!(found.before, "\nuser@host:~");
+        assert_eq!(exp.buffer(), "ls");
+
+        drop(exp);
+        drop(hub);
+        assert_eq!(leaked(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expect_timeout_and_eof() {
+        let hub = Arc::new(EventHub::new());
+        let mut exp = scripted(&hub);
+        exp.set_timeout(Duration::from_secs(5));
+        output(&hub, "Password: ").await;
+
+        let err = exp.expect(&Pattern::literal("$ ")).await.unwrap_err();
+        assert_eq!(err.code, SshErrorCode::Timeout);
+        assert_eq!(exp.buffer(), "Password: ");
+
+        output(&hub, "bye").await;
+        hub.finish();
+        let err = exp.expect(&Pattern::literal("$ ")).await.unwrap_err();
+        assert_eq!(err.code, SshErrorCode::NotReady);
+        let found = exp
+            .expect_any(&[Pattern::literal("$ "), Pattern::Eof])
+            .await
+            .unwrap();
+        assert_eq!((found.index, found.before.as_str()), (1, "Password: bye"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Кредитное управление потоком вывода: сессия отдаёт байты только в пределах подтверждённого окна.
+
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+/// Ручка управления потоком; потребитель вызывает `ack` для обработанных байт
+#[derive(Clone)]
+pub struct FlowControl {
+    inner: Arc<FlowInner>,
+}
+
+struct FlowInner {
+    credits: Semaphore,
+    window: u32,
+    outstanding: Mutex<u32>,
+}
+
+impl FlowControl {
+    pub(crate) fn new(window_bytes: u32) -> Self {
+        let window = window_bytes.max(1);
+        Self {
+            inner: Arc::new(FlowInner {
+                credits: Semaphore::new(window as usize),
+                window,
+                outstanding: Mutex::new(0),
+            }),
+        }
+    }
+
+    /// Размер окна, заданный при включении
+    pub fn window_size(&self) -> u32 {
+        self.inner.window
+    }
+
+    /// Сколько байт ещё можно отдать без подтверждений (`windowBytes` для `flow_control`)
+    pub fn window_bytes(&self) -> u32 {
+        u32::try_from(self.inner.credits.available_permits()).unwrap_or(u32::MAX)
+    }
+
+    /// Подтверждение обработанных байт; лишнее сверх отданного игнорируется
+    pub fn ack(&self, bytes: usize) {
+        let mut outstanding = self.inner.outstanding.lock().expect("poisoned");
+        let acked = u32::try_from(bytes).unwrap_or(u32::MAX).min(*outstanding);
+        *outstanding -= acked;
+        if acked > 0 && !self.inner.credits.is_closed() {
+            self.inner.credits.add_permits(acked as usize);
+        }
+    }
+
+    /// Ждёт кредитов под `bytes`; блок больше окна занимает всё окно целиком
+    pub(crate) async fn consume(&self, bytes: usize) {
+        let wanted = u32::try_from(bytes)
+            .unwrap_or(u32::MAX)
+            .min(self.inner.window);
+        if let Ok(permit) = self.inner.credits.acquire_many(wanted).await {
+            permit.forget();
+            *self.inner.outstanding.lock().expect("poisoned") += wanted;
+        }
+    }
+
+    /// Отключение или закрытие сессии: ожидающие `consume` больше не ждут
+    pub(crate) fn release(&self) {
+        self.inner.credits.close();
+    }
+}
+
+impl std::fmt::Debug for FlowControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlowControl")
+            .field("window_size", &self.window_size())
+            .field("window_bytes", &self.window_bytes())
+            .finish()
+    }
+}
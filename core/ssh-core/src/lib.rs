@@ -1,45 +1,179 @@
 #![allow(clippy::unused_async)]
 
+mod algorithms;
+pub mod asciicast;
+pub mod catalog;
+mod channel_requests;
+mod dial;
+mod errors;
+mod events;
+pub mod expect;
+mod flow;
+pub mod reconnect;
+mod scrollback;
+mod session_channel;
+mod signals;
+pub mod ssh_config;
+pub mod terminal;
+mod terminal_modes;
+#[cfg(test)]
+mod test_alloc;
+#[cfg(test)]
+mod test_server;
+mod wire;
+
+pub use algorithms::{AlgorithmPreferences, CryptoProfile, NegotiatedAlgorithms};
 #[cfg(test)]
 use base64::engine::general_purpose::STANDARD_NO_PAD;
 #[cfg(test)]
 use base64::Engine as _;
+pub use channel_requests::locale_env;
+use channel_requests::{ChannelRequest, PendingRequests};
+pub use dial::AddressFamily;
+use dial::DialOptions;
+use errors::Stage;
+use events::{ChannelPump, EventHub, EventSource};
+pub use flow::FlowControl;
 use russh::client;
 use russh::{Channel, CryptoVec, Disconnect};
+pub use scrollback::{ScrollbackLimit, MAX_SCROLLBACK_BYTES};
 use secrecy::{ExposeSecret, SecretString};
+use session_channel::SessionChannel;
 #[cfg(test)]
 use sha2::{Digest, Sha256};
+pub use signals::Signal;
 use ssh_key::HashAlg;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+pub use terminal_modes::{opcode, TerminalModes};
 use tokio::sync::broadcast;
 use tokio::sync::oneshot;
 use tokio::time::{timeout_at, Duration, Instant};
+pub use wire::TrafficStats;
+use wire::{Tapped, WireTap};
+use zeroize::Zeroizing;
 
 pub struct EventStream<T> {
-    snapshot: VecDeque<T>,
-    rx: broadcast::Receiver<T>,
+    snapshot: VecDeque<SequencedEvent>,
+    source: EventSource,
+    project: fn(SequencedEvent) -> T,
 }
 
-impl<T: Clone> EventStream<T> {
+impl<T> EventStream<T> {
+    /// Для lossless-подписки `Lagged` не возвращается никогда
     pub async fn recv(&mut self) -> Result<T, broadcast::error::RecvError> {
         if let Some(v) = self.snapshot.pop_front() {
-            return Ok(v);
+            return Ok((self.project)(v));
         }
-        self.rx.recv().await
+        let event = match &mut self.source {
+            // Вывод, стёртый при закрытии сессии, пропускается
+            EventSource::Broadcast(rx) => loop {
+                if let Some(event) = rx.recv().await?.get() {
+                    break event;
+                }
+            },
+            EventSource::Lossless(queue) => loop {
+                if let Some(v) = queue.pop() {
+                    break v;
+                }
+                if queue.is_finished() {
+                    break queue.pop().ok_or(broadcast::error::RecvError::Closed)?;
+                }
+                queue.wait().await;
+            },
+        };
+        Ok((self.project)(event))
     }
 
     pub fn try_recv(&mut self) -> Result<T, broadcast::error::TryRecvError> {
         if let Some(v) = self.snapshot.pop_front() {
-            return Ok(v);
+            return Ok((self.project)(v));
         }
-        self.rx.try_recv()
+        let event = match &mut self.source {
+            EventSource::Broadcast(rx) => loop {
+                if let Some(event) = rx.try_recv()?.get() {
+                    break event;
+                }
+            },
+            EventSource::Lossless(queue) => match queue.pop() {
+                Some(v) => v,
+                None if queue.is_finished() => return Err(broadcast::error::TryRecvError::Closed),
+                None => return Err(broadcast::error::TryRecvError::Empty),
+            },
+        };
+        Ok((self.project)(event))
     }
 }
 
+impl<T> Drop for EventStream<T> {
+    fn drop(&mut self) {
+        if let EventSource::Lossless(queue) = &self.source {
+            queue.detach();
+        }
+    }
+}
+
+/// Событие с порядковым номером (монотонный в пределах сессии) и временем публикации
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub timestamp: SystemTime,
+    pub event: SshEvent,
+}
+
+/// Сколько последних событий хранится для `resume_events`; старые вытесняются
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayLimits {
+    pub max_events: usize,
+    /// Суммарный объём `Stdout`/`Stderr` в буфере
+    pub max_bytes: usize,
+}
+
+impl Default for ReplayLimits {
+    fn default() -> Self {
+        Self {
+            max_events: 1024,
+            max_bytes: 1 << 20,
+        }
+    }
+}
+
+/// Способ доставки событий подписчику
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Delivery {
+    /// Общий broadcast: медленный подписчик получает `Lagged` и теряет события
+    #[default]
+    Lossy,
+    /// Собственная очередь: `Stdout`/`Stderr` не теряются, сессия ждёт, пока в очереди
+    /// больше `capacity_bytes` байт вывода
+    Lossless { capacity_bytes: usize },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubscribeOptions {
+    pub delivery: Delivery,
+    /// Добавить в snapshot накопленный scrollback (см. `SshSession::set_scrollback`)
+    pub scrollback: bool,
+}
+
 struct ClientHandler {
-    events_tx: broadcast::Sender<SshEvent>,
+    events: Arc<EventHub>,
+    generation: u64,
     host_key_fingerprint_tx: Mutex<Option<oneshot::Sender<String>>>,
+    tap: Arc<WireTap>,
+    requests: Arc<PendingRequests>,
+    pumps: HashMap<russh::ChannelId, ChannelPump>,
+}
+
+impl ClientHandler {
+    fn pump(&mut self, channel: russh::ChannelId) -> &ChannelPump {
+        let events = &self.events;
+        self.pumps
+            .entry(channel)
+            .or_insert_with(|| ChannelPump::spawn(Arc::clone(events)))
+    }
 }
 
 impl client::Handler for ClientHandler {
@@ -72,7 +206,7 @@ impl client::Handler for ClientHandler {
 
     fn data<'life0, 'life1, 'life2, 'async_trait>(
         &'life0 mut self,
-        _channel: russh::ChannelId,
+        channel: russh::ChannelId,
         data: &'life1 [u8],
         _session: &'life2 mut client::Session,
     ) -> std::pin::Pin<
@@ -84,18 +218,20 @@ impl client::Handler for ClientHandler {
         'life1: 'async_trait,
         'life2: 'async_trait,
     {
-        let events_tx = self.events_tx.clone();
+        self.tap.record_payload_received(data.len());
+        let pump = self.pump(channel);
         Box::pin(async move {
-            let _ = events_tx.send(SshEvent::Stdout {
-                data: data.to_vec(),
-            });
+            pump.push(SshEvent::Stdout {
+                data: data.to_vec().into(),
+            })
+            .await;
             Ok(())
         })
     }
 
     fn extended_data<'life0, 'life1, 'life2, 'async_trait>(
         &'life0 mut self,
-        _channel: russh::ChannelId,
+        channel: russh::ChannelId,
         ext: u32,
         data: &'life1 [u8],
         _session: &'life2 mut client::Session,
@@ -108,24 +244,119 @@ impl client::Handler for ClientHandler {
         'life1: 'async_trait,
         'life2: 'async_trait,
     {
-        let events_tx = self.events_tx.clone();
+        self.tap.record_payload_received(data.len());
+        let data = Zeroizing::new(data.to_vec());
+        let event = if ext == 1 {
+            SshEvent::Stderr { data }
+        } else {
+            SshEvent::Stdout { data }
+        };
+        let pump = self.pump(channel);
         Box::pin(async move {
-            if ext == 1 {
-                let _ = events_tx.send(SshEvent::Stderr {
-                    data: data.to_vec(),
-                });
-            } else {
-                let _ = events_tx.send(SshEvent::Stdout {
-                    data: data.to_vec(),
-                });
-            }
+            pump.push(event).await;
             Ok(())
         })
     }
 
-    fn exit_status<'life0, 'life1, 'async_trait>(
+    fn disconnected<'life0, 'async_trait>(
+        &'life0 mut self,
+        reason: client::DisconnectReason<Self::Error>,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<(), Self::Error>> + Send + 'async_trait>,
+    >
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+    {
+        Box::pin(async move {
+            // Вывод, принятый до разрыва, доходит до подписчиков раньше `Error`/`Closed`
+            for (_, pump) in self.pumps.drain() {
+                pump.flush().await;
+            }
+            self.events
+                .fail(&errors::disconnect_reason(&reason), self.generation);
+            match reason {
+                client::DisconnectReason::ReceivedDisconnect(_) => Ok(()),
+                client::DisconnectReason::Error(e) => Err(e),
+            }
+        })
+    }
+
+    fn channel_success<'life0, 'life1, 'async_trait>(
+        &'life0 mut self,
+        channel: russh::ChannelId,
+        _session: &'life1 mut client::Session,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<(), Self::Error>> + Send + 'async_trait>,
+    >
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+    {
+        self.requests.complete(channel.into());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn channel_failure<'life0, 'life1, 'async_trait>(
+        &'life0 mut self,
+        channel: russh::ChannelId,
+        _session: &'life1 mut client::Session,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<(), Self::Error>> + Send + 'async_trait>,
+    >
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+    {
+        // Отказ в запросе канала не рвёт транспорт: сообщаем, но состояние не меняем
+        self.events.send(self.requests.rejected(channel.into()));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn channel_open_failure<'life0, 'life1, 'life2, 'life3, 'async_trait>(
         &'life0 mut self,
         _channel: russh::ChannelId,
+        reason: russh::ChannelOpenFailure,
+        _description: &'life1 str,
+        _language: &'life2 str,
+        _session: &'life3 mut client::Session,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<(), Self::Error>> + Send + 'async_trait>,
+    >
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+        'life3: 'async_trait,
+    {
+        self.events
+            .send(SshEvent::from(&errors::channel_open_failure(reason)));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn channel_close<'life0, 'life1, 'async_trait>(
+        &'life0 mut self,
+        channel: russh::ChannelId,
+        _session: &'life1 mut client::Session,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<(), Self::Error>> + Send + 'async_trait>,
+    >
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+    {
+        // Задача насоса дочитает буфер и завершится сама
+        self.pumps.remove(&channel);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn exit_status<'life0, 'life1, 'async_trait>(
+        &'life0 mut self,
+        channel: russh::ChannelId,
         exit_status: u32,
         _session: &'life1 mut client::Session,
     ) -> std::pin::Pin<
@@ -136,22 +367,26 @@ impl client::Handler for ClientHandler {
         'life0: 'async_trait,
         'life1: 'async_trait,
     {
-        let events_tx = self.events_tx.clone();
+        // Через насос канала, чтобы `Exit` не обогнал ещё не доставленный вывод
+        let pump = self.pump(channel);
         Box::pin(async move {
-            let _ = events_tx.send(SshEvent::Exit {
+            pump.push(SshEvent::Exit {
                 exit_code: exit_status as i32,
                 signal: None,
-            });
+                core_dumped: false,
+                error_message: None,
+            })
+            .await;
             Ok(())
         })
     }
 
     fn exit_signal<'life0, 'life1, 'life2, 'life3, 'async_trait>(
         &'life0 mut self,
-        _channel: russh::ChannelId,
+        channel: russh::ChannelId,
         signal_name: russh::Sig,
-        _core_dumped: bool,
-        _error_message: &'life1 str,
+        core_dumped: bool,
+        error_message: &'life1 str,
         _lang_tag: &'life2 str,
         _session: &'life3 mut client::Session,
     ) -> std::pin::Pin<
@@ -164,12 +399,10 @@ impl client::Handler for ClientHandler {
         'life2: 'async_trait,
         'life3: 'async_trait,
     {
-        let events_tx = self.events_tx.clone();
+        let exit = signals::exit_event(&signal_name, core_dumped, error_message);
+        let pump = self.pump(channel);
         Box::pin(async move {
-            let _ = events_tx.send(SshEvent::Exit {
-                exit_code: 128,
-                signal: Some(format!("{signal_name:?}")),
-            });
+            pump.push(exit).await;
             Ok(())
         })
     }
@@ -187,6 +420,97 @@ pub struct Pty {
     pub cols: u16,
     pub rows: u16,
     pub term: String,
+    /// Размер в пикселях для sixel/графики; 0 — не указан
+    pub pixel_width: u32,
+    pub pixel_height: u32,
+    pub modes: TerminalModes,
+}
+
+impl Default for Pty {
+    fn default() -> Self {
+        Self {
+            cols: 80,
+            rows: 24,
+            term: "xterm-256color".to_string(),
+            pixel_width: 0,
+            pixel_height: 0,
+            modes: TerminalModes::platform_default(),
+        }
+    }
+}
+
+/// Метаданные установленного подключения; задержки в миллисекундах
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Идентификационная строка сервера, например `SSH-2.0-OpenSSH_9.6`
+    pub server_banner: Option<String>,
+    pub algorithms: Option<NegotiatedAlgorithms>,
+    pub local_addr: SocketAddr,
+    /// Адрес, к которому подключились (из всех разрешённых для имени хоста)
+    pub remote_addr: SocketAddr,
+    /// От начала `connect` до установления TCP
+    pub connect_latency_ms: u32,
+    /// От установления TCP до получения ключа хоста (обмен версиями и ключами)
+    pub handshake_latency_ms: u32,
+    /// Последняя попытка аутентификации, успешная или нет
+    pub auth_latency_ms: Option<u32>,
+}
+
+/// Параметры подключения: таймауты и обнаружение «мёртвого» собеседника
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionOptions {
+    /// Общий таймаут TCP-подключения и рукопожатия
+    pub timeout_ms: u32,
+    /// Интервал `keepalive@openssh.com` (`keepAliveSec`); `None` — keepalive выключен
+    pub keepalive_interval_ms: Option<u32>,
+    /// Сколько keepalive подряд может остаться без ответа, прежде чем сессия закроется с `Timeout`
+    pub keepalive_max: u32,
+    /// Закрыть сессию с `Timeout`, если в обе стороны ничего не передавалось столько времени
+    pub inactivity_timeout_ms: Option<u32>,
+    /// Какие адреса из DNS пробовать и в каком порядке
+    pub address_family: AddressFamily,
+    /// Сдвиг между параллельными попытками подключения к разным адресам (RFC 8305)
+    pub attempt_delay_ms: u32,
+    /// Таймаут подключения к одному адресу
+    pub attempt_timeout_ms: u32,
+    /// Предпочтения kex/ключа хоста/шифра/MAC/сжатия; по умолчанию профиль `compatible`
+    pub algorithms: AlgorithmPreferences,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 10_000,
+            keepalive_interval_ms: None,
+            keepalive_max: 3,
+            inactivity_timeout_ms: None,
+            address_family: AddressFamily::default(),
+            attempt_delay_ms: 250,
+            attempt_timeout_ms: 5_000,
+            algorithms: AlgorithmPreferences::default(),
+        }
+    }
+}
+
+impl SessionOptions {
+    fn dial_options(&self) -> DialOptions {
+        DialOptions {
+            family: self.address_family,
+            attempt_delay: Duration::from_millis(self.attempt_delay_ms.into()),
+            attempt_timeout: Duration::from_millis(self.attempt_timeout_ms.into()),
+        }
+    }
+
+    fn client_config(&self) -> Result<client::Config, SshError> {
+        let millis = |ms: u32| Duration::from_millis(ms.into());
+        Ok(client::Config {
+            keepalive_interval: self.keepalive_interval_ms.map(millis),
+            keepalive_max: self.keepalive_max.max(1) as usize,
+            inactivity_timeout: self.inactivity_timeout_ms.map(millis),
+            preferred: self.algorithms.to_preferred()?,
+            ..client::Config::default()
+        })
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -212,24 +536,42 @@ pub enum SshEvent {
     Status {
         state: SshState,
     },
+    /// Каждая копия вывода затирается при удалении: в очередях, replay, scrollback и у подписчика
     Stdout {
-        data: Vec<u8>,
+        data: Zeroizing<Vec<u8>>,
     },
     Stderr {
-        data: Vec<u8>,
+        data: Zeroizing<Vec<u8>>,
     },
     HostKeyPrompt {
         fingerprint: String,
         reason: HostKeyReason,
     },
+    /// По сигналу `exit_code` = 128 + номер сигнала, `signal` — имя без `SIG` (`TERM`)
     Exit {
         exit_code: i32,
         signal: Option<String>,
+        core_dumped: bool,
+        error_message: Option<String>,
     },
     Error {
         code: SshErrorCode,
         message: String,
         retryable: bool,
+        retry_after_ms: Option<u32>,
+    },
+    /// Алгоритмы, согласованные при обмене ключами; публикуется до `HostKeyPrompt`
+    Negotiated {
+        algorithms: NegotiatedAlgorithms,
+    },
+    /// Сервер отказал в переменной окружения (обычно фильтр `AcceptEnv`); канал продолжает работу
+    EnvRejected {
+        name: String,
+    },
+    /// Супервизор переподключения начнёт попытку `attempt` через `delay_ms`
+    Reconnecting {
+        attempt: u32,
+        delay_ms: u32,
     },
 }
 
@@ -291,22 +633,75 @@ impl std::fmt::Display for SshErrorCode {
     }
 }
 
+/// Задержка перед повтором для `RATE_LIMITED`, если сервер не подсказал свою (SRS §9.7.9)
+pub const DEFAULT_RATE_LIMIT_RETRY_AFTER_MS: u32 = 5_000;
+/// Рекомендуемая задержка перед повтором после `TIMEOUT`
+pub const DEFAULT_TIMEOUT_RETRY_AFTER_MS: u32 = 1_000;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SshError {
     pub code: SshErrorCode,
     pub message: String,
     pub retryable: bool,
+    /// `retryAfterMs`: всегда задан для `RateLimited`, для `Timeout` — рекомендация
+    pub retry_after_ms: Option<u32>,
+    /// Цепочка причин от внешней к внутренней (адреса, ошибки сокета, коды сервера)
+    pub causes: Vec<ErrorCause>,
+}
+
+/// Слой, на котором возникла причина ошибки
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CauseKind {
+    Dns,
+    Tcp,
+    Io,
+    Protocol,
+    Auth,
+    ServerDisconnect,
+}
+
+/// Звено цепочки причин; `detail` не содержит секретов и текста от сервера
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorCause {
+    pub kind: CauseKind,
+    pub detail: String,
+}
+
+impl ErrorCause {
+    pub fn new(kind: CauseKind, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            detail: detail.into(),
+        }
+    }
 }
 
 impl SshError {
     pub fn new(code: SshErrorCode, message: impl Into<String>, retryable: bool) -> Self {
+        let retry_after_ms = match code {
+            SshErrorCode::RateLimited => Some(DEFAULT_RATE_LIMIT_RETRY_AFTER_MS),
+            SshErrorCode::Timeout if retryable => Some(DEFAULT_TIMEOUT_RETRY_AFTER_MS),
+            _ => None,
+        };
         Self {
             code,
             message: message.into(),
             retryable,
+            retry_after_ms,
+            causes: Vec::new(),
         }
     }
 
+    pub fn with_retry_after(mut self, ms: u32) -> Self {
+        self.retry_after_ms = Some(ms);
+        self
+    }
+
+    pub fn with_cause(mut self, cause: ErrorCause) -> Self {
+        self.causes.push(cause);
+        self
+    }
+
     fn not_ready() -> Self {
         Self::new(
             SshErrorCode::NotReady,
@@ -323,11 +718,22 @@ impl SshError {
         )
     }
 
-    fn invalid_state() -> Self {
+    pub(crate) fn invalid_state() -> Self {
         Self::new(SshErrorCode::InvalidState, "Invalid state", false)
     }
 }
 
+impl From<&SshError> for SshEvent {
+    fn from(error: &SshError) -> Self {
+        SshEvent::Error {
+            code: error.code,
+            message: error.message.clone(),
+            retryable: error.retryable,
+            retry_after_ms: error.retry_after_ms,
+        }
+    }
+}
+
 impl std::fmt::Display for SshError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}: {}", self.code, self.message)
@@ -343,17 +749,19 @@ pub struct PrivateKeyRef {
 
 /// Основная структура SSH-сессии
 pub struct SshSession {
-    state: SshState,
-    events_tx: broadcast::Sender<SshEvent>,
+    events: Arc<EventHub>,
     pending_host_key: Option<HostKeyPromptEvent>,
     server_fingerprint: Option<String>,
+    info: Option<ConnectionInfo>,
+    tap: Option<Arc<WireTap>>,
+    requests: Arc<PendingRequests>,
     handle: Option<client::Handle<ClientHandler>>,
-    channel: Option<Channel<client::Msg>>,
+    channel: Option<SessionChannel>,
     username: Option<String>,
 }
 
 impl SshSession {
-    fn can_transition(from: SshState, to: SshState) -> bool {
+    pub(crate) fn can_transition(from: SshState, to: SshState) -> bool {
         if from == to {
             return true;
         }
@@ -376,39 +784,60 @@ impl SshSession {
     }
 
     pub fn new() -> Self {
+        Self::with_events(Arc::new(EventHub::new()))
+    }
+
+    fn with_events(events: Arc<EventHub>) -> Self {
         SshSession {
-            state: SshState::Init,
-            events_tx: broadcast::Sender::new(256),
+            events,
             pending_host_key: None,
             server_fingerprint: None,
+            info: None,
+            tap: None,
+            requests: Arc::new(PendingRequests::default()),
             handle: None,
             channel: None,
             username: None,
         }
     }
 
+    /// Отвязывает сессию от общего hub: дальнейшие `disconnect`/`Drop` не трогают его подписчиков
+    pub(crate) fn detach_events(&mut self) {
+        self.events = Arc::new(EventHub::new());
+    }
+
     pub fn server_fingerprint(&self) -> Option<String> {
         self.server_fingerprint.clone()
     }
 
+    /// Согласованные алгоритмы текущего подключения (см. `SshEvent::Negotiated`)
+    pub fn negotiated_algorithms(&self) -> Option<&NegotiatedAlgorithms> {
+        self.info.as_ref()?.algorithms.as_ref()
+    }
+
+    /// Метаданные подключения для аудита; `None` до успешного `connect`
+    pub fn connection_info(&self) -> Option<&ConnectionInfo> {
+        self.info.as_ref()
+    }
+
+    /// Счётчики трафика для оценки выигрыша от сжатия; `None` до успешного `connect`
+    pub fn traffic_stats(&self) -> Option<TrafficStats> {
+        self.tap.as_ref().map(|tap| tap.stats())
+    }
+
     /// Обновление состояния сессии
     pub fn transition(&mut self, new_state: SshState) -> Result<(), SshError> {
-        if !Self::can_transition(self.state, new_state) {
-            return Err(SshError::invalid_state());
-        }
-
-        if new_state == self.state {
-            return Ok(());
-        }
+        self.events.transition(new_state)
+    }
 
-        self.state = new_state;
-        let _ = self.events_tx.send(SshEvent::Status { state: new_state });
-        Ok(())
+    /// Текущее состояние; может смениться на `Closed` без вызова методов (асинхронный сбой)
+    pub fn state(&self) -> SshState {
+        self.events.session_state()
     }
 
     /// Проверка, разрешены ли операции ввода
     pub fn is_ready(&self) -> bool {
-        self.state == SshState::Ready
+        self.state() == SshState::Ready
     }
 
     pub async fn connect(
@@ -417,28 +846,84 @@ impl SshSession {
         user: &str,
         timeout_ms: u32,
     ) -> Result<Self, SshError> {
-        let mut session = SshSession::new();
-        session.transition(SshState::Connecting)?;
+        let options = SessionOptions {
+            timeout_ms,
+            ..SessionOptions::default()
+        };
+        Self::connect_with(host, port, user, &options).await
+    }
+
+    pub async fn connect_with(
+        host: &str,
+        port: u16,
+        user: &str,
+        options: &SessionOptions,
+    ) -> Result<Self, SshError> {
+        Self::connect_on(Arc::new(EventHub::new()), host, port, user, options).await
+    }
+
+    /// Подключение, публикующее события в существующий hub (переподключение под супервизором).
+    /// При ошибке сессия отвязывается от hub, чтобы её закрытие не затронуло подписчиков
+    pub(crate) async fn connect_on(
+        events: Arc<EventHub>,
+        host: &str,
+        port: u16,
+        user: &str,
+        options: &SessionOptions,
+    ) -> Result<Self, SshError> {
+        let mut session = SshSession::with_events(events);
+        match session.establish(host, port, user, options).await {
+            Ok(()) => Ok(session),
+            Err(e) => {
+                session.detach_events();
+                Err(e)
+            }
+        }
+    }
 
-        let deadline = Instant::now() + Duration::from_millis(timeout_ms.into());
+    async fn establish(
+        &mut self,
+        host: &str,
+        port: u16,
+        user: &str,
+        options: &SessionOptions,
+    ) -> Result<(), SshError> {
+        self.transition(SshState::Connecting)?;
+
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(options.timeout_ms.into());
 
         let (tx, rx) = oneshot::channel::<String>();
+        let tap = Arc::new(WireTap::default());
         let handler = ClientHandler {
-            events_tx: session.events_tx.clone(),
+            events: Arc::clone(&self.events),
+            generation: self.events.generation(),
             host_key_fingerprint_tx: Mutex::new(Some(tx)),
+            tap: Arc::clone(&tap),
+            requests: Arc::clone(&self.requests),
+            pumps: HashMap::new(),
         };
 
-        let config = Arc::new(client::Config::default());
-        let handle = timeout_at(deadline, client::connect(config, (host, port), handler))
+        let config = Arc::new(options.client_config()?);
+        let connecting = async {
+            let socket = dial::connect_tcp(host, port, options.dial_options()).await?;
+            let connected = Instant::now();
+            let local_addr = socket
+                .local_addr()
+                .map_err(|e| errors::io_error(&e, Stage::Connect))?;
+            let remote_addr = socket
+                .peer_addr()
+                .map_err(|e| errors::io_error(&e, Stage::Connect))?;
+            client::connect_stream(config, Tapped::new(socket, Arc::clone(&tap)), handler)
+                .await
+                .map(|handle| (handle, connected, local_addr, remote_addr))
+                .map_err(|e| errors::russh_error(&e, Stage::Connect))
+        };
+        let (handle, connected, local_addr, remote_addr) = timeout_at(deadline, connecting)
             .await
             .map_err(|_| SshError::new(SshErrorCode::Timeout, "Connect timeout", true))?
-            .map_err(|e| {
-                SshError::new(
-                    SshErrorCode::ConnectFailed,
-                    format!("russh connect failed: {e:?}"),
-                    true,
-                )
-            })?;
+            // Если сервер прислал DISCONNECT во время рукопожатия, его причина точнее ошибки russh
+            .map_err(|e| self.events.failure().unwrap_or(e))?;
 
         let fingerprint = match timeout_at(deadline, rx).await {
             Ok(Ok(f)) => f,
@@ -460,14 +945,29 @@ impl SshSession {
             }
         };
 
-        session.handle = Some(handle);
-        session.username = Some(user.to_string());
-        session.server_fingerprint = Some(fingerprint.clone());
-        session.pending_host_key = Some(HostKeyPromptEvent {
+        // Проверка ключа хоста идёт внутри обмена ключами, так что оба KEXINIT уже прошли
+        let info = ConnectionInfo {
+            server_banner: tap.server_banner(),
+            algorithms: tap.negotiated(),
+            local_addr,
+            remote_addr,
+            connect_latency_ms: elapsed_ms(started, connected),
+            handshake_latency_ms: elapsed_ms(connected, Instant::now()),
+            auth_latency_ms: None,
+        };
+        if let Some(algorithms) = info.algorithms.clone() {
+            self.events.send(SshEvent::Negotiated { algorithms });
+        }
+        self.info = Some(info);
+        self.tap = Some(tap);
+        self.handle = Some(handle);
+        self.username = Some(user.to_string());
+        self.server_fingerprint = Some(fingerprint.clone());
+        self.pending_host_key = Some(HostKeyPromptEvent {
             fingerprint,
             reason: HostKeyReason::New,
         });
-        Ok(session)
+        Ok(())
     }
 
     pub async fn verify_host_key(
@@ -475,7 +975,7 @@ impl SshSession {
         policy: HostKeyPolicy,
         known: Option<KnownHostEntry>,
     ) -> Result<HostKeyDecision, SshError> {
-        if self.state != SshState::Connecting && self.state != SshState::HostKeyPrompt {
+        if !matches!(self.state(), SshState::Connecting | SshState::HostKeyPrompt) {
             return Err(SshError::invalid_state());
         }
 
@@ -544,7 +1044,7 @@ impl SshSession {
                 });
                 self.transition(SshState::HostKeyPrompt)?;
                 let pending = self.pending_host_key.as_ref().expect("just set");
-                let _ = self.events_tx.send(SshEvent::HostKeyPrompt {
+                self.events.send(SshEvent::HostKeyPrompt {
                     fingerprint: pending.fingerprint.clone(),
                     reason: pending.reason,
                 });
@@ -566,12 +1066,17 @@ impl SshSession {
         handle
             .data(channel.id(), CryptoVec::from(data))
             .await
-            .map_err(|_| SshError::new(SshErrorCode::InternalError, "Send failed", true))?;
+            .map_err(|_| errors::russh_error(&russh::Error::SendError, Stage::Channel))?;
+        if let Some(tap) = &self.tap {
+            tap.record_payload_sent(data.len());
+        }
         Ok(())
     }
 
+    /// `SecretString` затирается при выходе. russh 0.49 принимает пароль только как `String`
+    /// и держит эту копию до конца аутентификации, освобождая без затирания; обойти это нельзя
     pub async fn auth_password(&mut self, password: SecretString) -> Result<(), SshError> {
-        if self.state != SshState::Ready {
+        if self.state() != SshState::Ready {
             return Err(SshError::invalid_state());
         }
         if self.pending_host_key.is_some() {
@@ -587,26 +1092,26 @@ impl SshSession {
             .as_mut()
             .ok_or_else(|| SshError::new(SshErrorCode::InternalError, "Missing handle", false))?;
 
+        let started = Instant::now();
         let ok = handle
-            .authenticate_password(username, password.expose_secret().to_string())
+            .authenticate_password(username, password.expose_secret().as_str())
             .await
-            .map_err(|e| {
-                SshError::new(
-                    SshErrorCode::AuthFailed,
-                    format!("auth_password failed: {e:?}"),
-                    true,
-                )
-            })?;
+            .map_err(|e| errors::russh_error(&e, Stage::Auth))?;
+        if let Some(info) = self.info.as_mut() {
+            info.auth_latency_ms = Some(elapsed_ms(started, Instant::now()));
+        }
         if !ok {
-            return Err(SshError::new(
-                SshErrorCode::AuthFailed,
-                "Authentication failed",
-                false,
-            ));
+            return Err(
+                SshError::new(SshErrorCode::AuthFailed, "Authentication failed", false).with_cause(
+                    ErrorCause::new(CauseKind::Auth, "password rejected by server"),
+                ),
+            );
         }
         Ok(())
     }
 
+    /// Пока не реализовано: всегда `InternalError` «Not implemented». Ключ и passphrase затираются
+    /// вместе с `SecretString`, расшифрованный ключ нигде не создаётся
     pub async fn auth_key(
         &mut self,
         _key: PrivateKeyRef,
@@ -616,7 +1121,7 @@ impl SshSession {
     }
 
     pub async fn host_key_accept(&mut self) -> Result<(), SshError> {
-        if self.state != SshState::HostKeyPrompt {
+        if self.state() != SshState::HostKeyPrompt {
             return Err(SshError::invalid_state());
         }
         self.pending_host_key = None;
@@ -625,108 +1130,308 @@ impl SshSession {
     }
 
     pub async fn host_key_reject(&mut self) -> Result<(), SshError> {
-        if self.state != SshState::HostKeyPrompt {
+        if self.state() != SshState::HostKeyPrompt {
             return Err(SshError::invalid_state());
         }
         self.disconnect().await
     }
 
-    pub async fn open_pty(&mut self, _pty: Pty) -> Result<(), SshError> {
-        if self.state != SshState::Ready {
+    pub async fn open_pty(&mut self, pty: Pty) -> Result<(), SshError> {
+        self.open_pty_with_env(pty, &[]).await
+    }
+
+    /// `open_pty` с запросами `env` до запуска оболочки (например, [`locale_env`]).
+    /// Отвергнутые сервером (`AcceptEnv`) переменные приходят как `SshEvent::EnvRejected`.
+    /// Как и `exec`, закрывает уже открытый канал
+    pub async fn open_pty_with_env(
+        &mut self,
+        pty: Pty,
+        env: &[(String, String)],
+    ) -> Result<(), SshError> {
+        let channel = self.open_session_channel(env).await?;
+        self.close_channel().await;
+        let id = u32::from(channel.id());
+        self.requests.push(id, ChannelRequest::Pty);
+        channel
+            .request_pty(
+                true,
+                &pty.term,
+                pty.cols as u32,
+                pty.rows as u32,
+                pty.pixel_width,
+                pty.pixel_height,
+                &pty.modes.to_russh(),
+            )
+            .await
+            .map_err(|e| errors::russh_error(&e, Stage::Channel))?;
+        self.requests.push(id, ChannelRequest::Shell);
+        channel
+            .request_shell(true)
+            .await
+            .map_err(|e| errors::russh_error(&e, Stage::Channel))?;
+
+        self.channel = Some(SessionChannel::spawn(channel));
+        Ok(())
+    }
+
+    /// Запуск команды без PTY; вывод и завершение приходят теми же событиями, что и у оболочки.
+    /// Уже открытый канал PTY или exec закрывается: операции ввода идут в один канал
+    pub async fn exec(&mut self, command: &str, env: &[(String, String)]) -> Result<(), SshError> {
+        let channel = self.open_session_channel(env).await?;
+        self.close_channel().await;
+        self.requests
+            .push(channel.id().into(), ChannelRequest::Exec);
+        channel
+            .exec(true, command)
+            .await
+            .map_err(|e| errors::russh_error(&e, Stage::Channel))?;
+
+        self.channel = Some(SessionChannel::spawn(channel));
+        Ok(())
+    }
+
+    /// Новый канал `session` с уже отправленными запросами `env`
+    async fn open_session_channel(
+        &self,
+        env: &[(String, String)],
+    ) -> Result<Channel<client::Msg>, SshError> {
+        if self.state() != SshState::Ready {
             return Err(SshError::invalid_state());
         }
         if self.pending_host_key.is_some() {
             return Err(SshError::invalid_state());
         }
 
-        let pty = _pty;
         let handle = self
             .handle
             .as_ref()
             .ok_or_else(|| SshError::new(SshErrorCode::InternalError, "Missing handle", false))?;
-        let channel = handle.channel_open_session().await.map_err(|e| {
-            SshError::new(
-                SshErrorCode::InternalError,
-                format!("channel_open_session failed: {e:?}"),
-                true,
-            )
-        })?;
+        let channel = handle
+            .channel_open_session()
+            .await
+            .map_err(|e| errors::russh_error(&e, Stage::Channel))?;
+
+        for (name, value) in env {
+            self.requests
+                .push(channel.id().into(), ChannelRequest::Env(name.clone()));
+            channel
+                .set_env(true, name.as_str(), value.as_str())
+                .await
+                .map_err(|e| errors::russh_error(&e, Stage::Channel))?;
+        }
+        Ok(channel)
+    }
 
-        channel
-            .request_pty(true, &pty.term, pty.cols as u32, pty.rows as u32, 0, 0, &[])
+    /// Закрывает текущий канал PTY или exec, если он открыт
+    async fn close_channel(&mut self) {
+        if let Some(channel) = self.channel.take() {
+            self.requests.forget(channel.id().into());
+            let _ = channel.close().await;
+        }
+    }
+
+    pub async fn resize(&mut self, cols: u16, rows: u16) -> Result<(), SshError> {
+        self.resize_with_pixels(cols, rows, 0, 0).await
+    }
+
+    /// `resize` с размером в пикселях для терминалов с поддержкой графики
+    pub async fn resize_with_pixels(
+        &mut self,
+        cols: u16,
+        rows: u16,
+        pixel_width: u32,
+        pixel_height: u32,
+    ) -> Result<(), SshError> {
+        self.ready_channel()?
+            .window_change(cols as u32, rows as u32, pixel_width, pixel_height)
             .await
-            .map_err(|e| {
-                SshError::new(
-                    SshErrorCode::InternalError,
-                    format!("request_pty failed: {e:?}"),
-                    true,
-                )
-            })?;
-        channel.request_shell(true).await.map_err(|e| {
-            SshError::new(
-                SshErrorCode::InternalError,
-                format!("request_shell failed: {e:?}"),
-                true,
-            )
-        })?;
+            .map_err(|e| errors::russh_error(&e, Stage::Channel))
+    }
 
-        self.channel = Some(channel);
-        Ok(())
+    /// Запрос `signal`: процесс останавливается без разрыва соединения
+    pub async fn send_signal(&mut self, signal: Signal) -> Result<(), SshError> {
+        self.ready_channel()?
+            .signal(signal.to_russh())
+            .await
+            .map_err(|e| errors::russh_error(&e, Stage::Channel))
     }
 
-    pub async fn resize(&mut self, _cols: u16, _rows: u16) -> Result<(), SshError> {
+    /// Закрывает stdin удалённого процесса; вывод продолжает приходить
+    pub async fn send_eof(&mut self) -> Result<(), SshError> {
+        self.ready_channel()?
+            .eof()
+            .await
+            .map_err(|e| errors::russh_error(&e, Stage::Channel))
+    }
+
+    fn ready_channel(&self) -> Result<&SessionChannel, SshError> {
         if !self.is_ready() {
             return Err(SshError::not_ready());
         }
-        let channel = self
-            .channel
+        self.channel
             .as_ref()
-            .ok_or_else(|| SshError::new(SshErrorCode::NotReady, "PTY not open", true))?;
-        channel
-            .window_change(_cols as u32, _rows as u32, 0, 0)
-            .await
-            .map_err(|e| {
-                SshError::new(
-                    SshErrorCode::InternalError,
-                    format!("window_change failed: {e:?}"),
-                    true,
-                )
-            })?;
-        Ok(())
+            .ok_or_else(|| SshError::new(SshErrorCode::NotReady, "PTY not open", true))
     }
 
     pub async fn disconnect(&mut self) -> Result<(), SshError> {
-        if let Some(channel) = self.channel.take() {
-            let _ = channel.close().await;
+        // Иначе насос канала, ждущий медленного подписчика, не отдаст хвост вывода и не завершится
+        self.events.release();
+        // Сессия могла уже закрыться из-за асинхронного сбоя; тогда остаётся только уборка.
+        // `Closing` выставляется до отправки DISCONNECT, чтобы ответный обрыв не стал `Error`
+        let already_closed = self.state() == SshState::Closed;
+        if !already_closed {
+            self.transition(SshState::Closing)?;
         }
+        self.close_channel().await;
         if let Some(handle) = self.handle.take() {
             let _ = handle.disconnect(Disconnect::ByApplication, "", "").await;
         }
-        self.transition(SshState::Closing)?;
-        self.transition(SshState::Closed)?;
+        if !already_closed {
+            self.transition(SshState::Closed)?;
+        }
         self.pending_host_key = None;
         self.server_fingerprint = None;
+        self.info = None;
+        self.tap = None;
+        self.requests.clear();
         self.username = None;
+        self.events.finish();
         Ok(())
     }
 
+    /// Включает кредитное управление доставкой: вывод отдаётся подписчикам, только пока есть
+    /// неподтверждённые кредиты. Окно SSH-канала кредиты не задают: russh 0.49 шлёт
+    /// `WINDOW_ADJUST` сам. Без `ack` заполняется буфер канала, и тогда ждёт весь цикл сессии:
+    /// ввод, keepalive и другие каналы стоят до следующего `ack`
+    pub fn enable_flow_control(&mut self, window_bytes: u32) -> FlowControl {
+        let flow = FlowControl::new(window_bytes);
+        self.events.set_flow_control(Some(flow.clone()));
+        flow
+    }
+
+    pub fn disable_flow_control(&mut self) {
+        self.events.set_flow_control(None);
+    }
+
+    pub fn flow_control(&self) -> Option<FlowControl> {
+        self.events.flow_control()
+    }
+
+    /// Подписка через общий broadcast: подходит для статусов, вывод может теряться при отставании
     pub fn subscribe_events(&self) -> EventStream<SshEvent> {
-        let mut snapshot = VecDeque::new();
-        snapshot.push_back(SshEvent::Status { state: self.state });
-        if self.state == SshState::HostKeyPrompt {
-            if let Some(pending) = self.pending_host_key.as_ref() {
-                snapshot.push_back(SshEvent::HostKeyPrompt {
-                    fingerprint: pending.fingerprint.clone(),
-                    reason: pending.reason,
-                });
-            }
-        }
+        self.subscribe_events_with(SubscribeOptions::default())
+    }
+
+    pub fn subscribe_events_with(&self, options: SubscribeOptions) -> EventStream<SshEvent> {
+        self.subscribe_projected(options, |sequenced| sequenced.event)
+    }
+
+    /// Подписка с номерами событий; snapshot текущего состояния помечен `seq` последнего
+    /// опубликованного события, так что с него можно возобновиться через `resume_events`
+    pub fn subscribe_sequenced(&self, options: SubscribeOptions) -> EventStream<SequencedEvent> {
+        self.subscribe_projected(options, |sequenced| sequenced)
+    }
+
+    fn subscribe_projected<T>(
+        &self,
+        options: SubscribeOptions,
+        project: fn(SequencedEvent) -> T,
+    ) -> EventStream<T> {
+        open_stream(
+            &self.events,
+            options,
+            self.pending_host_key.as_ref(),
+            project,
+        )
+    }
+
+    /// Возобновляет подписку после события `after_seq`: сначала отдаются сохранённые события
+    /// с большим номером, затем живой поток без пропусков и повторов.
+    /// Если часть событий уже вытеснена из буфера, возвращает `BadRequest` — нужна новая подписка
+    pub fn resume_events(
+        &self,
+        after_seq: u64,
+        options: SubscribeOptions,
+    ) -> Result<EventStream<SequencedEvent>, SshError> {
+        resume_stream(&self.events, after_seq, options)
+    }
+
+    /// Номер последнего опубликованного события (0 — событий ещё не было)
+    pub fn last_event_seq(&self) -> u64 {
+        self.events.last_seq()
+    }
+
+    /// Включает scrollback вывода для поздних подписчиков (`SubscribeOptions::scrollback`).
+    /// Буфер живёт только в памяти и затирается при `disconnect`; `None` отключает и стирает его
+    pub fn set_scrollback(&mut self, limit: Option<ScrollbackLimit>) {
+        self.events.set_scrollback(limit);
+    }
+
+    pub fn set_replay_limits(&mut self, limits: ReplayLimits) {
+        self.events.set_replay_limits(limits);
+    }
+}
 
-        EventStream {
-            snapshot,
-            rx: self.events_tx.subscribe(),
+fn open_stream<T>(
+    events: &EventHub,
+    options: SubscribeOptions,
+    pending_host_key: Option<&HostKeyPromptEvent>,
+    project: fn(SequencedEvent) -> T,
+) -> EventStream<T> {
+    let subscription = events
+        .subscribe(options.delivery, None, options.scrollback)
+        .expect("subscription without resume never fails");
+
+    let stamp = |event| SequencedEvent {
+        seq: subscription.last_seq,
+        timestamp: SystemTime::now(),
+        event,
+    };
+    let mut snapshot = VecDeque::new();
+    snapshot.push_back(stamp(SshEvent::Status {
+        state: subscription.state,
+    }));
+    if subscription.state == SshState::HostKeyPrompt {
+        if let Some(pending) = pending_host_key {
+            snapshot.push_back(stamp(SshEvent::HostKeyPrompt {
+                fingerprint: pending.fingerprint.clone(),
+                reason: pending.reason,
+            }));
         }
     }
+    snapshot.extend(subscription.scrollback.into_iter().map(stamp));
+
+    EventStream {
+        snapshot,
+        source: subscription.source,
+        project,
+    }
+}
+
+fn resume_stream(
+    events: &EventHub,
+    after_seq: u64,
+    options: SubscribeOptions,
+) -> Result<EventStream<SequencedEvent>, SshError> {
+    let subscription = events
+        .subscribe(options.delivery, Some(after_seq), false)
+        .map_err(|_| {
+            SshError::new(
+                SshErrorCode::BadRequest,
+                format!("events after seq {after_seq} are not available for replay"),
+                false,
+            )
+        })?;
+    Ok(EventStream {
+        snapshot: subscription.replay.into(),
+        source: subscription.source,
+        project: |sequenced| sequenced,
+    })
+}
+
+/// Миллисекунды между отметками для `ConnectionInfo`, с насыщением до `u32::MAX`
+fn elapsed_ms(from: Instant, to: Instant) -> u32 {
+    u32::try_from(to.duration_since(from).as_millis()).unwrap_or(u32::MAX)
 }
 
 impl Default for SshSession {
@@ -735,6 +1440,12 @@ impl Default for SshSession {
     }
 }
 
+impl Drop for SshSession {
+    fn drop(&mut self) {
+        self.events.release();
+    }
+}
+
 /// Событие запроса проверки host key
 #[derive(Debug)]
 pub struct HostKeyPromptEvent {
@@ -745,6 +1456,8 @@ pub struct HostKeyPromptEvent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server::TestServer;
+    use tokio::time::timeout;
 
     fn seed_pending_host_key(session: &mut SshSession, seed: &[u8]) {
         session.transition(SshState::Connecting).unwrap();
@@ -757,7 +1470,7 @@ mod tests {
     #[tokio::test]
     async fn test_session_state_transitions() {
         let mut session = SshSession::new();
-        assert_eq!(session.state, SshState::Init);
+        assert_eq!(session.state(), SshState::Init);
 
         assert!(matches!(
             session.transition(SshState::Ready),
@@ -765,10 +1478,10 @@ mod tests {
         ));
 
         session.transition(SshState::Connecting).unwrap();
-        assert_eq!(session.state, SshState::Connecting);
+        assert_eq!(session.state(), SshState::Connecting);
 
         session.transition(SshState::Ready).unwrap();
-        assert_eq!(session.state, SshState::Ready);
+        assert_eq!(session.state(), SshState::Ready);
     }
 
     #[tokio::test]
@@ -808,7 +1521,7 @@ mod tests {
 
         let result = session.verify_host_key(HostKeyPolicy::Strict, None).await;
         assert!(matches!(result, Err(e) if e.code == SshErrorCode::HostkeyUnknown));
-        assert_eq!(session.state, SshState::Closed);
+        assert_eq!(session.state(), SshState::Closed);
     }
 
     #[tokio::test]
@@ -835,7 +1548,7 @@ mod tests {
             .verify_host_key(HostKeyPolicy::AcceptNew, Some(known))
             .await;
         assert!(matches!(result, Err(e) if e.code == SshErrorCode::HostkeyChanged));
-        assert_eq!(session.state, SshState::Closed);
+        assert_eq!(session.state(), SshState::Closed);
     }
 
     #[tokio::test]
@@ -858,4 +1571,584 @@ mod tests {
         session.host_key_accept().await.unwrap();
         assert!(session.is_ready());
     }
+
+    fn stdout(data: &[u8]) -> SshEvent {
+        SshEvent::Stdout {
+            data: data.to_vec().into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lossless_subscriber_gets_every_byte_with_backpressure() {
+        let session = SshSession::new();
+        let mut rx = session.subscribe_events_with(SubscribeOptions {
+            delivery: Delivery::Lossless { capacity_bytes: 8 },
+            ..SubscribeOptions::default()
+        });
+        assert!(matches!(rx.try_recv(), Ok(SshEvent::Status { .. })));
+
+        let events = Arc::clone(&session.events);
+        let producer = tokio::spawn(async move {
+            for i in 0..32u8 {
+                events.send_data(stdout(&[i; 4])).await;
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(
+            !producer.is_finished(),
+            "producer must wait for the consumer"
+        );
+
+        for i in 0..32u8 {
+            assert_eq!(rx.recv().await.unwrap(), stdout(&[i; 4]));
+        }
+        producer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stdin_flows_while_lossless_subscriber_is_full() {
+        const OUTPUT: usize = 64 * 1024;
+        let server = TestServer::start(OUTPUT).await;
+        let mut session = server.session().await;
+        let mut stalled = session.subscribe_events_with(SubscribeOptions {
+            delivery: Delivery::Lossless {
+                capacity_bytes: 1024,
+            },
+            ..SubscribeOptions::default()
+        });
+        let mut watcher = session.subscribe_events();
+        session.open_pty(Pty::default()).await.unwrap();
+
+        // Второй пакет вывода уже не помещается в очередь, которую никто не читает
+        let mut chunks = 0;
+        while chunks < 2 {
+            if let SshEvent::Stdout { .. } = watcher.recv().await.unwrap() {
+                chunks += 1;
+            }
+        }
+        timeout(Duration::from_secs(5), session.write_stdin(b"ping"))
+            .await
+            .expect("session loop is not blocked by the subscriber")
+            .unwrap();
+        timeout(
+            Duration::from_secs(5),
+            server.log.wait_until(|_, stdin| stdin == b"ping"),
+        )
+        .await
+        .expect("server received stdin");
+
+        let mut received = 0;
+        while received < OUTPUT {
+            if let SshEvent::Stdout { data } = stalled.recv().await.unwrap() {
+                received += data.len();
+            }
+        }
+        assert_eq!(received, OUTPUT);
+        session.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_slow_lossless_subscriber_gets_every_byte_in_bounded_memory() {
+        const OUTPUT: usize = 16 << 20;
+        let server = TestServer::start(OUTPUT).await;
+        let mut session = server.session().await;
+        let mut slow = session.subscribe_events_with(SubscribeOptions {
+            delivery: Delivery::Lossless {
+                capacity_bytes: 64 * 1024,
+            },
+            ..SubscribeOptions::default()
+        });
+        let base = test_alloc::start_peak();
+        session.open_pty(Pty::default()).await.unwrap();
+
+        let mut received = 0;
+        let mut chunks = 0;
+        while received < OUTPUT {
+            if let SshEvent::Stdout { data } = slow.recv().await.unwrap() {
+                assert!(data.iter().all(|&b| b == b'x'));
+                received += data.len();
+                chunks += 1;
+                if chunks % 64 == 0 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        }
+        assert_eq!(received, OUTPUT);
+        // Сервер шлёт быстрее, чем читает подписчик, но ждёт он в буферах, а не в памяти клиента
+        let peak = test_alloc::peak() - base;
+        assert!(
+            peak < 8 << 20,
+            "peak {peak} bytes for {OUTPUT} bytes of output"
+        );
+        session.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lossy_subscriber_may_lag_while_lossless_does_not() {
+        let session = SshSession::new();
+        let mut lossy = session.subscribe_events();
+        let mut lossless = session.subscribe_events_with(SubscribeOptions {
+            delivery: Delivery::Lossless {
+                capacity_bytes: 1 << 20,
+            },
+            ..SubscribeOptions::default()
+        });
+
+        for i in 0..300u16 {
+            session.events.send_data(stdout(&i.to_be_bytes())).await;
+        }
+
+        assert!(matches!(lossy.try_recv(), Ok(SshEvent::Status { .. })));
+        assert!(matches!(
+            lossy.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(_))
+        ));
+
+        assert!(matches!(lossless.try_recv(), Ok(SshEvent::Status { .. })));
+        for i in 0..300u16 {
+            assert_eq!(lossless.try_recv().unwrap(), stdout(&i.to_be_bytes()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_releases_blocked_output_and_closes_stream() {
+        let mut session = SshSession::new();
+        session.transition(SshState::Connecting).unwrap();
+        let mut rx = session.subscribe_events_with(SubscribeOptions {
+            delivery: Delivery::Lossless { capacity_bytes: 4 },
+            ..SubscribeOptions::default()
+        });
+
+        let events = Arc::clone(&session.events);
+        let producer = tokio::spawn(async move {
+            events.send_data(stdout(b"aaaa")).await;
+            events.send_data(stdout(b"bbbb")).await;
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!producer.is_finished());
+
+        session.disconnect().await.unwrap();
+        producer.await.unwrap();
+
+        let mut received = Vec::new();
+        loop {
+            match rx.recv().await {
+                Ok(ev) => received.push(ev),
+                Err(broadcast::error::RecvError::Closed) => break,
+                Err(e) => panic!("unexpected {e:?}"),
+            }
+        }
+        assert_eq!(
+            received,
+            vec![
+                SshEvent::Status {
+                    state: SshState::Connecting
+                },
+                stdout(b"aaaa"),
+                stdout(b"bbbb"),
+                SshEvent::Status {
+                    state: SshState::Closing
+                },
+                SshEvent::Status {
+                    state: SshState::Closed
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flow_control_blocks_until_ack() {
+        let mut session = SshSession::new();
+        let flow = session.enable_flow_control(8);
+        let mut rx = session.subscribe_events();
+        assert!(matches!(rx.try_recv(), Ok(SshEvent::Status { .. })));
+
+        let events = Arc::clone(&session.events);
+        let producer = tokio::spawn(async move {
+            for i in 0..4u8 {
+                events.send_data(stdout(&[i; 4])).await;
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(flow.window_bytes(), 0);
+        assert_eq!(rx.try_recv().unwrap(), stdout(&[0; 4]));
+        assert_eq!(rx.try_recv().unwrap(), stdout(&[1; 4]));
+        assert!(rx.try_recv().is_err(), "no credits left");
+
+        flow.ack(4);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(rx.try_recv().unwrap(), stdout(&[2; 4]));
+        assert!(rx.try_recv().is_err());
+
+        // Подтверждение сверх отданного не расширяет окно
+        flow.ack(1000);
+        producer.await.unwrap();
+        assert_eq!(rx.try_recv().unwrap(), stdout(&[3; 4]));
+        flow.ack(4);
+        assert_eq!(flow.window_bytes(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_disable_flow_control_releases_waiters() {
+        let mut session = SshSession::new();
+        let _flow = session.enable_flow_control(2);
+
+        let events = Arc::clone(&session.events);
+        let producer = tokio::spawn(async move {
+            events.send_data(stdout(b"ab")).await;
+            events.send_data(stdout(b"cd")).await;
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!producer.is_finished());
+
+        session.disable_flow_control();
+        producer.await.unwrap();
+        assert!(session.flow_control().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_output_beyond_flow_window_keeps_memory_bounded() {
+        const WINDOW: u32 = 64 * 1024;
+        const OUTPUT: usize = 24 << 20;
+        let server = TestServer::start(OUTPUT).await;
+        let mut session = server.session().await;
+        let flow = session.enable_flow_control(WINDOW);
+        let mut rx = session.subscribe_events();
+        let base = test_alloc::start_peak();
+        session.open_pty(Pty::default()).await.unwrap();
+
+        // Без `ack` доставка останавливается на окне кредитов
+        let mut received = 0;
+        while let Ok(event) = timeout(Duration::from_millis(300), rx.recv()).await {
+            if let SshEvent::Stdout { data } = event.unwrap() {
+                received += data.len();
+            }
+        }
+        assert!(received > 0 && received <= WINDOW as usize);
+        flow.ack(received);
+
+        while received < OUTPUT {
+            if let SshEvent::Stdout { data } = rx.recv().await.unwrap() {
+                received += data.len();
+                flow.ack(data.len());
+            }
+        }
+        // Весь вывод прошёл через сессию, но в памяти единовременно была лишь его малая часть
+        let peak = test_alloc::peak() - base;
+        assert!(
+            peak < 8 << 20,
+            "peak {peak} bytes for {OUTPUT} bytes of output"
+        );
+        session.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_events_are_sequenced_and_timestamped() {
+        let mut session = SshSession::new();
+        let mut rx = session.subscribe_sequenced(SubscribeOptions::default());
+        let snapshot = rx.try_recv().unwrap();
+        assert_eq!(snapshot.seq, 0);
+
+        session.transition(SshState::Connecting).unwrap();
+        session.events.send_data(stdout(b"hi")).await;
+        let first = rx.try_recv().unwrap();
+        let second = rx.try_recv().unwrap();
+        assert_eq!((first.seq, second.seq), (1, 2));
+        assert!(first.timestamp <= second.timestamp);
+        assert_eq!(second.event, stdout(b"hi"));
+        assert_eq!(session.last_event_seq(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_resume_replays_events_after_offset() {
+        let mut session = SshSession::new();
+        session.transition(SshState::Connecting).unwrap();
+        for i in 0..3u8 {
+            session.events.send_data(stdout(&[i])).await;
+        }
+
+        let mut rx = session
+            .resume_events(2, SubscribeOptions::default())
+            .unwrap();
+        assert_eq!(rx.try_recv().unwrap().event, stdout(&[1]));
+        assert_eq!(rx.try_recv().unwrap().event, stdout(&[2]));
+        assert!(rx.try_recv().is_err());
+
+        session.events.send_data(stdout(&[3])).await;
+        let live = rx.try_recv().unwrap();
+        assert_eq!((live.seq, live.event), (5, stdout(&[3])));
+
+        let mut caught_up = session
+            .resume_events(session.last_event_seq(), SubscribeOptions::default())
+            .unwrap();
+        assert!(caught_up.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resume_fails_when_events_were_evicted() {
+        let mut session = SshSession::new();
+        session.set_replay_limits(ReplayLimits {
+            max_events: 2,
+            max_bytes: 1024,
+        });
+        for i in 0..4u8 {
+            session.events.send_data(stdout(&[i])).await;
+        }
+
+        assert!(matches!(
+            session.resume_events(1, SubscribeOptions::default()),
+            Err(e) if e.code == SshErrorCode::BadRequest
+        ));
+        assert!(matches!(
+            session.resume_events(10, SubscribeOptions::default()),
+            Err(e) if e.code == SshErrorCode::BadRequest
+        ));
+        let mut rx = session
+            .resume_events(2, SubscribeOptions::default())
+            .unwrap();
+        assert_eq!(rx.try_recv().unwrap().seq, 3);
+
+        session.disconnect().await.unwrap();
+        assert!(session
+            .resume_events(2, SubscribeOptions::default())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_late_subscriber_gets_scrollback_until_disconnect() {
+        let mut session = SshSession::new();
+        session.events.send_data(stdout(b"before\n")).await;
+        session.set_scrollback(Some(ScrollbackLimit::Lines(1)));
+        session.events.send_data(stdout(b"one\n")).await;
+        session.events.send_data(stdout(b"two\n")).await;
+
+        let with_scrollback = SubscribeOptions {
+            scrollback: true,
+            ..SubscribeOptions::default()
+        };
+        let mut rx = session.subscribe_events_with(with_scrollback.clone());
+        assert!(matches!(rx.try_recv(), Ok(SshEvent::Status { .. })));
+        assert_eq!(rx.try_recv().unwrap(), stdout(b"two\n"));
+        assert!(rx.try_recv().is_err());
+
+        let mut plain = session.subscribe_events();
+        assert!(matches!(plain.try_recv(), Ok(SshEvent::Status { .. })));
+        assert!(plain.try_recv().is_err());
+
+        session.disconnect().await.unwrap();
+        let mut late = session.subscribe_events_with(with_scrollback);
+        assert!(matches!(late.try_recv(), Ok(SshEvent::Status { .. })));
+        assert!(late.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_wipes_output_left_in_broadcast_ring() {
+        let mut session = SshSession::new();
+        session.transition(SshState::Connecting).unwrap();
+        session.set_scrollback(Some(ScrollbackLimit::Lines(10)));
+        let mut lagging = session.subscribe_events();
+        let mut reader = session.subscribe_events();
+        session
+            .events
+            .send_data(SshEvent::Stdout {
+                data: test_alloc::with_secret(b"token=", b"\n"),
+            })
+            .await;
+        while reader.try_recv().is_ok() {}
+
+        session.disconnect().await.unwrap();
+        // Вывод стёрт, хотя отставший подписчик его ещё не прочитал
+        assert_eq!(test_alloc::leaked(), 0);
+        for state in [SshState::Closing, SshState::Closed] {
+            assert_eq!(reader.try_recv().unwrap(), SshEvent::Status { state });
+        }
+        assert!(matches!(
+            reader.try_recv(),
+            Err(broadcast::error::TryRecvError::Empty)
+        ));
+
+        // Стёртый вывод пропускается без `Lagged`, статусы после него на месте
+        assert!(matches!(lagging.try_recv(), Ok(SshEvent::Status { .. })));
+        for state in [SshState::Closing, SshState::Closed] {
+            assert_eq!(lagging.try_recv().unwrap(), SshEvent::Status { state });
+        }
+        assert!(matches!(
+            lagging.try_recv(),
+            Err(broadcast::error::TryRecvError::Empty)
+        ));
+
+        drop((lagging, reader, session));
+        assert_eq!(test_alloc::leaked(), 0);
+    }
+
+    fn handler_for(session: &SshSession) -> ClientHandler {
+        ClientHandler {
+            events: Arc::clone(&session.events),
+            generation: session.events.generation(),
+            host_key_fingerprint_tx: Mutex::new(None),
+            tap: Arc::new(WireTap::default()),
+            requests: Arc::clone(&session.requests),
+            pumps: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_timeout_emits_error_and_closes() {
+        use client::Handler as _;
+
+        let mut session = SshSession::new();
+        session.transition(SshState::Connecting).unwrap();
+        session.transition(SshState::Ready).unwrap();
+        let mut rx = session.subscribe_events_with(SubscribeOptions {
+            delivery: Delivery::Lossless { capacity_bytes: 64 },
+            ..SubscribeOptions::default()
+        });
+        assert!(matches!(rx.try_recv(), Ok(SshEvent::Status { .. })));
+
+        let mut handler = handler_for(&session);
+        let result = handler
+            .disconnected(client::DisconnectReason::Error(
+                russh::Error::KeepaliveTimeout,
+            ))
+            .await;
+        assert!(result.is_err());
+
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            SshEvent::Error {
+                code: SshErrorCode::Timeout,
+                message: "Keepalive timeout".to_string(),
+                retryable: true,
+                retry_after_ms: Some(DEFAULT_TIMEOUT_RETRY_AFTER_MS),
+            }
+        );
+        for state in [SshState::Closing, SshState::Closed] {
+            assert_eq!(rx.recv().await.unwrap(), SshEvent::Status { state });
+        }
+        assert!(matches!(
+            rx.recv().await,
+            Err(broadcast::error::RecvError::Closed)
+        ));
+        assert_eq!(session.state(), SshState::Closed);
+        assert!(matches!(
+            session.write_stdin(b"x").await,
+            Err(e) if e.code == SshErrorCode::NotReady
+        ));
+        session.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_after_application_close_is_not_an_error() {
+        use client::Handler as _;
+
+        let mut session = SshSession::new();
+        session.transition(SshState::Connecting).unwrap();
+        let mut rx = session.subscribe_events();
+        let mut handler = handler_for(&session);
+
+        session.transition(SshState::Closing).unwrap();
+        handler
+            .disconnected(client::DisconnectReason::ReceivedDisconnect(
+                client::RemoteDisconnectInfo {
+                    reason_code: Disconnect::ByApplication,
+                    message: String::new(),
+                    lang_tag: String::new(),
+                },
+            ))
+            .await
+            .unwrap();
+        session.disconnect().await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            received.push(ev);
+        }
+        assert!(!received
+            .iter()
+            .any(|ev| matches!(ev, SshEvent::Error { .. })));
+        assert_eq!(session.state(), SshState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_exec_closes_open_pty_channel() {
+        let server = TestServer::start(0).await;
+        let mut session = server.session().await;
+        session.open_pty(Pty::default()).await.unwrap();
+        session.exec("uptime", &[]).await.unwrap();
+
+        let wait = server.log.wait_until(|requests, _| {
+            let shell = requests.iter().find_map(|r| r.strip_prefix("shell "));
+            shell.is_some_and(|id| requests.contains(&format!("close {id}")))
+                && requests
+                    .iter()
+                    .any(|r| r.starts_with("exec ") && r.ends_with(" uptime"))
+        });
+        timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("PTY channel closed and exec started");
+
+        session.write_stdin(b"input").await.unwrap();
+        timeout(
+            Duration::from_secs(5),
+            server.log.wait_until(|_, stdin| stdin == b"input"),
+        )
+        .await
+        .expect("stdin goes to the exec channel");
+        session.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_channel_operations_require_ready_session() {
+        let mut session = SshSession::new();
+        for result in [
+            session.send_signal(Signal::Int).await,
+            session.send_eof().await,
+        ] {
+            assert_eq!(result.unwrap_err().code, SshErrorCode::NotReady);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_closed_port_is_refused() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let err = SshSession::connect("127.0.0.1", port, "user", 2000)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code, SshErrorCode::ConnectFailed);
+        assert_eq!(err.message, "Connection refused");
+        assert!(err.retryable);
+        assert_eq!(err.retry_after_ms, None);
+        assert_eq!(err.causes[0].kind, CauseKind::Tcp);
+        assert_eq!(
+            err.causes[0].detail,
+            format!("127.0.0.1:{port}: connection refused")
+        );
+    }
+
+    #[test]
+    fn test_session_options_map_to_client_config() {
+        let config = SessionOptions::default().client_config().unwrap();
+        assert_eq!(config.keepalive_interval, None);
+        assert_eq!(config.inactivity_timeout, None);
+
+        let config = SessionOptions {
+            keepalive_interval_ms: Some(15_000),
+            keepalive_max: 0,
+            inactivity_timeout_ms: Some(600_000),
+            algorithms: CryptoProfile::Modern.preferences(),
+            ..SessionOptions::default()
+        }
+        .client_config()
+        .unwrap();
+        assert_eq!(config.preferred.cipher[0], russh::cipher::CHACHA20_POLY1305);
+        assert_eq!(config.keepalive_interval, Some(Duration::from_secs(15)));
+        assert_eq!(config.keepalive_max, 1);
+        assert_eq!(config.inactivity_timeout, Some(Duration::from_secs(600)));
+    }
 }
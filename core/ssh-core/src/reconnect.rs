@@ -0,0 +1,578 @@
+//! Опциональный супервизор переподключения поверх `SshSession`.
+//!
+//! После повторяемого сбоя супервизор переподключается с экспоненциальной задержкой и jitter,
+//! сверяет host key с тем же известным отпечатком (смена ключа — окончательная ошибка),
+//! заново аутентифицируется через `CredentialProvider` и открывает PTY с последним размером.
+//! Подписчики видят один непрерывный поток событий: `Error`, `Reconnecting`, `Status`, вывод.
+
+use crate::events::EventHub;
+use crate::{
+    open_stream, resume_stream, ConnectionInfo, EventStream, HostKeyPolicy, KnownHostEntry,
+    PrivateKeyRef, Pty, SequencedEvent, SessionOptions, Signal, SshError, SshErrorCode, SshEvent,
+    SshSession, SshState, SubscribeOptions, TrafficStats,
+};
+use rand::Rng;
+use secrecy::SecretString;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// Параметры backoff между попытками
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay_ms: u32,
+    pub max_delay_ms: u32,
+    pub multiplier: u32,
+    /// Доля задержки (0.0–1.0), которая выбирается случайно, чтобы клиенты не шли волной
+    pub jitter: f64,
+    /// `None` — без ограничения числа попыток
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: 500,
+            max_delay_ms: 30_000,
+            multiplier: 2,
+            jitter: 0.5,
+            max_attempts: Some(10),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Задержка перед попыткой `attempt` (с 1); подсказка сервера `retry_after_ms` не сокращается
+    pub fn delay_ms(&self, attempt: u32, retry_after_ms: Option<u32>) -> u32 {
+        let exp = attempt.saturating_sub(1).min(31);
+        let base = u64::from(self.initial_delay_ms)
+            .saturating_mul(u64::from(self.multiplier.max(1)).saturating_pow(exp))
+            .min(u64::from(self.max_delay_ms));
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let random = rand::thread_rng().gen_range(0.0..=jitter);
+        let delay = (base as f64 * (1.0 - random)) as u32;
+        delay.max(retry_after_ms.unwrap_or(0))
+    }
+}
+
+/// Учётные данные для повторной аутентификации
+pub enum Credentials {
+    Password(SecretString),
+    /// Пока не поддерживается: `auth_key` не реализован, супервизор отвечает `BadRequest`
+    Key {
+        key: PrivateKeyRef,
+        passphrase: Option<SecretString>,
+    },
+}
+
+pub type CredentialFuture = Pin<Box<dyn Future<Output = Result<Credentials, SshError>> + Send>>;
+
+/// Источник учётных данных; вызывается на каждую попытку, секреты не кешируются супервизором
+pub trait CredentialProvider: Send + Sync {
+    fn credentials(&self, attempt: u32) -> CredentialFuture;
+}
+
+impl<F> CredentialProvider for F
+where
+    F: Fn(u32) -> CredentialFuture + Send + Sync,
+{
+    fn credentials(&self, attempt: u32) -> CredentialFuture {
+        self(attempt)
+    }
+}
+
+/// Куда и как подключаться; `known_host` — запись, с которой сверяется каждая попытка
+#[derive(Clone, Debug)]
+pub struct ReconnectTarget {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub options: SessionOptions,
+    /// Политика первого подключения; `Ask` не поддерживается — супервизор неинтерактивен
+    pub host_key_policy: HostKeyPolicy,
+    pub known_host: Option<KnownHostEntry>,
+    pub pty: Pty,
+    /// Переменные `env` для каждого нового канала, например [`crate::locale_env`]
+    pub env: Vec<(String, String)>,
+}
+
+struct Shared {
+    events: Arc<EventHub>,
+    session: tokio::sync::Mutex<SshSession>,
+    target: ReconnectTarget,
+    /// Отпечаток первого подключения, если `known_host` не был задан
+    known_host: KnownHostEntry,
+    pty: Mutex<Pty>,
+    policy: ReconnectPolicy,
+    credentials: Arc<dyn CredentialProvider>,
+}
+
+/// Сессия под супервизором переподключения
+pub struct ReconnectingSession {
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl ReconnectingSession {
+    /// Первое подключение не повторяется: его ошибка возвращается как есть
+    pub async fn connect(
+        target: ReconnectTarget,
+        policy: ReconnectPolicy,
+        credentials: Arc<dyn CredentialProvider>,
+    ) -> Result<Self, SshError> {
+        if target.host_key_policy == HostKeyPolicy::Ask {
+            return Err(SshError::new(
+                SshErrorCode::BadRequest,
+                "Reconnect supervisor requires a non-interactive host key policy",
+                false,
+            ));
+        }
+        let events = Arc::new(EventHub::new());
+        events.supervise();
+
+        let session = establish(
+            &events,
+            &target,
+            target.host_key_policy,
+            target.known_host.as_ref(),
+            &target.pty,
+            credentials.as_ref(),
+            0,
+        )
+        .await?;
+        let known_host = KnownHostEntry {
+            fingerprint: session.server_fingerprint().unwrap_or_default(),
+        };
+
+        let shared = Arc::new(Shared {
+            events,
+            session: tokio::sync::Mutex::new(session),
+            pty: Mutex::new(target.pty.clone()),
+            target,
+            known_host,
+            policy,
+            credentials,
+        });
+        let task = tokio::spawn(supervise(Arc::clone(&shared)));
+        Ok(Self { shared, task })
+    }
+
+    pub fn state(&self) -> SshState {
+        self.shared.events.session_state()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state() == SshState::Ready
+    }
+
+    pub fn subscribe_events(&self) -> EventStream<SshEvent> {
+        self.subscribe_events_with(SubscribeOptions::default())
+    }
+
+    pub fn subscribe_events_with(&self, options: SubscribeOptions) -> EventStream<SshEvent> {
+        open_stream(&self.shared.events, options, None, |sequenced| {
+            sequenced.event
+        })
+    }
+
+    pub fn resume_events(
+        &self,
+        after_seq: u64,
+        options: SubscribeOptions,
+    ) -> Result<EventStream<SequencedEvent>, SshError> {
+        resume_stream(&self.shared.events, after_seq, options)
+    }
+
+    /// Метаданные текущего подключения; после переподключения — уже нового
+    pub async fn connection_info(&self) -> Option<ConnectionInfo> {
+        self.shared.session.lock().await.connection_info().cloned()
+    }
+
+    /// Счётчики текущего подключения; с каждым переподключением начинаются с нуля
+    pub async fn traffic_stats(&self) -> Option<TrafficStats> {
+        self.shared.session.lock().await.traffic_stats()
+    }
+
+    /// Во время переподключения возвращает `NotReady`
+    pub async fn write_stdin(&self, data: &[u8]) -> Result<(), SshError> {
+        self.shared.session.lock().await.write_stdin(data).await
+    }
+
+    /// Размер запоминается даже без связи: PTY новой сессии откроется уже с ним
+    pub async fn resize(&self, cols: u16, rows: u16) -> Result<(), SshError> {
+        self.resize_with_pixels(cols, rows, 0, 0).await
+    }
+
+    pub async fn resize_with_pixels(
+        &self,
+        cols: u16,
+        rows: u16,
+        pixel_width: u32,
+        pixel_height: u32,
+    ) -> Result<(), SshError> {
+        {
+            let mut pty = self.shared.pty.lock().expect("poisoned");
+            pty.cols = cols;
+            pty.rows = rows;
+            pty.pixel_width = pixel_width;
+            pty.pixel_height = pixel_height;
+        }
+        self.shared
+            .session
+            .lock()
+            .await
+            .resize_with_pixels(cols, rows, pixel_width, pixel_height)
+            .await
+    }
+
+    pub async fn send_signal(&self, signal: Signal) -> Result<(), SshError> {
+        self.shared.session.lock().await.send_signal(signal).await
+    }
+
+    pub async fn send_eof(&self) -> Result<(), SshError> {
+        self.shared.session.lock().await.send_eof().await
+    }
+
+    /// Останавливает супервизор и закрывает текущую сессию
+    pub async fn disconnect(self) -> Result<(), SshError> {
+        self.task.abort();
+        self.shared.session.lock().await.disconnect().await
+    }
+}
+
+impl Drop for ReconnectingSession {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn supervise(shared: Arc<Shared>) {
+    loop {
+        let failure = shared.events.wait_failure().await;
+        if !failure.retryable || !reconnect(&shared, &failure).await {
+            let _ = shared.session.lock().await.disconnect().await;
+            return;
+        }
+    }
+}
+
+/// `false` — попытки исчерпаны или ошибка не повторяемая; событие `Error` уже опубликовано
+async fn reconnect(shared: &Shared, failure: &SshError) -> bool {
+    let mut retry_after_ms = failure.retry_after_ms;
+    let mut attempt = 0u32;
+    loop {
+        attempt += 1;
+        if shared.policy.max_attempts.is_some_and(|max| attempt > max) {
+            shared.events.send(SshEvent::from(&SshError::new(
+                SshErrorCode::ConnectFailed,
+                "Reconnect attempts exhausted",
+                false,
+            )));
+            return false;
+        }
+
+        let delay_ms = shared.policy.delay_ms(attempt, retry_after_ms);
+        shared
+            .events
+            .send(SshEvent::Reconnecting { attempt, delay_ms });
+        tokio::time::sleep(Duration::from_millis(delay_ms.into())).await;
+        shared.events.restart();
+
+        let pty = shared.pty.lock().expect("poisoned").clone();
+        match establish(
+            &shared.events,
+            &shared.target,
+            HostKeyPolicy::Strict,
+            Some(&shared.known_host),
+            &pty,
+            shared.credentials.as_ref(),
+            attempt,
+        )
+        .await
+        {
+            Ok(session) => {
+                let mut previous = std::mem::replace(&mut *shared.session.lock().await, session);
+                previous.detach_events();
+                let _ = previous.disconnect().await;
+                return true;
+            }
+            Err(e) => {
+                // Сбой транспорта во время попытки уже опубликован hub'ом
+                if shared.events.take_failure().is_none() {
+                    shared.events.send(SshEvent::from(&e));
+                }
+                if !e.retryable {
+                    return false;
+                }
+                retry_after_ms = e.retry_after_ms;
+            }
+        }
+    }
+}
+
+/// Полная установка сессии; при ошибке сессия отвязана от hub и закрыта
+async fn establish(
+    events: &Arc<EventHub>,
+    target: &ReconnectTarget,
+    policy: HostKeyPolicy,
+    known: Option<&KnownHostEntry>,
+    pty: &Pty,
+    credentials: &dyn CredentialProvider,
+    attempt: u32,
+) -> Result<SshSession, SshError> {
+    let mut session = SshSession::connect_on(
+        Arc::clone(events),
+        &target.host,
+        target.port,
+        &target.user,
+        &target.options,
+    )
+    .await?;
+    let env = target.env.as_slice();
+    match authenticate(&mut session, policy, known, pty, env, credentials, attempt).await {
+        Ok(()) => Ok(session),
+        Err(e) => {
+            session.detach_events();
+            let _ = session.disconnect().await;
+            Err(e)
+        }
+    }
+}
+
+async fn authenticate(
+    session: &mut SshSession,
+    policy: HostKeyPolicy,
+    known: Option<&KnownHostEntry>,
+    pty: &Pty,
+    env: &[(String, String)],
+    credentials: &dyn CredentialProvider,
+    attempt: u32,
+) -> Result<(), SshError> {
+    // Проверяем до `verify_host_key`: тот при отказе закрыл бы общий hub
+    let fingerprint = session.server_fingerprint().unwrap_or_default();
+    match (policy, known) {
+        (_, Some(known)) if known.fingerprint != fingerprint => {
+            return Err(SshError::new(
+                SshErrorCode::HostkeyChanged,
+                "Host key changed since the known entry",
+                false,
+            ))
+        }
+        (HostKeyPolicy::Strict, None) => {
+            return Err(SshError::new(
+                SshErrorCode::HostkeyUnknown,
+                "Host key unknown (policy=strict)",
+                false,
+            ))
+        }
+        _ => {}
+    }
+    session.verify_host_key(policy, known.cloned()).await?;
+
+    match credentials.credentials(attempt).await? {
+        Credentials::Password(password) => session.auth_password(password).await?,
+        Credentials::Key { .. } => {
+            return Err(SshError::new(
+                SshErrorCode::BadRequest,
+                "Reconnect supervisor does not support key credentials",
+                false,
+            ))
+        }
+    }
+    session.open_pty_with_env(pty.clone(), env).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast::error::RecvError;
+
+    #[test]
+    fn test_backoff_grows_with_jitter_and_cap() {
+        let policy = ReconnectPolicy {
+            initial_delay_ms: 100,
+            max_delay_ms: 1_000,
+            multiplier: 2,
+            jitter: 0.5,
+            max_attempts: None,
+        };
+        for _ in 0..100 {
+            let first = policy.delay_ms(1, None);
+            assert!((50..=100).contains(&first), "{first}");
+            let third = policy.delay_ms(3, None);
+            assert!((200..=400).contains(&third), "{third}");
+            let capped = policy.delay_ms(30, None);
+            assert!((500..=1_000).contains(&capped), "{capped}");
+        }
+        assert_eq!(policy.delay_ms(1, Some(5_000)), 5_000);
+
+        let exact = ReconnectPolicy {
+            jitter: 0.0,
+            ..policy
+        };
+        assert_eq!(exact.delay_ms(2, None), 200);
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_reports_attempts_and_gives_up() {
+        // Порт без слушателя: каждая попытка получает `Connection refused`
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let events = Arc::new(EventHub::new());
+        events.supervise();
+        events.transition(SshState::Connecting).unwrap();
+        events.transition(SshState::Ready).unwrap();
+
+        let credentials: Arc<dyn CredentialProvider> =
+            Arc::new(|_attempt: u32| -> CredentialFuture {
+                Box::pin(async { Ok(Credentials::Password(SecretString::new("pw".into()))) })
+            });
+        let shared = Arc::new(Shared {
+            events: Arc::clone(&events),
+            session: tokio::sync::Mutex::new(SshSession::with_events(Arc::clone(&events))),
+            target: ReconnectTarget {
+                host: "127.0.0.1".to_string(),
+                port,
+                user: "user".to_string(),
+                options: SessionOptions {
+                    timeout_ms: 1_000,
+                    ..SessionOptions::default()
+                },
+                host_key_policy: HostKeyPolicy::Strict,
+                known_host: None,
+                pty: Pty::default(),
+                env: Vec::new(),
+            },
+            known_host: KnownHostEntry {
+                fingerprint: "SHA256:known".to_string(),
+            },
+            pty: Mutex::new(Pty::default()),
+            policy: ReconnectPolicy {
+                initial_delay_ms: 1,
+                max_delay_ms: 2,
+                multiplier: 2,
+                jitter: 0.0,
+                max_attempts: Some(2),
+            },
+            credentials,
+        });
+        let session = ReconnectingSession {
+            task: tokio::spawn(supervise(Arc::clone(&shared))),
+            shared,
+        };
+        let mut rx = session.subscribe_events_with(SubscribeOptions {
+            delivery: crate::Delivery::Lossless { capacity_bytes: 64 },
+            ..SubscribeOptions::default()
+        });
+        assert!(matches!(rx.recv().await, Ok(SshEvent::Status { .. })));
+
+        events.fail(
+            &SshError::new(SshErrorCode::ConnectFailed, "Connection lost", true),
+            events.generation(),
+        );
+
+        let mut received = Vec::new();
+        loop {
+            match rx.recv().await {
+                Ok(ev) => received.push(ev),
+                Err(RecvError::Closed) => break,
+                Err(e) => panic!("unexpected {e:?}"),
+            }
+        }
+        let attempts: Vec<_> = received
+            .iter()
+            .filter_map(|ev| match ev {
+                SshEvent::Reconnecting { attempt, .. } => Some(*attempt),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(attempts, vec![1, 2]);
+        assert!(received.contains(&SshEvent::Error {
+            code: SshErrorCode::ConnectFailed,
+            message: "Connection refused".to_string(),
+            retryable: true,
+            retry_after_ms: None,
+        }));
+        assert!(received.contains(&SshEvent::Error {
+            code: SshErrorCode::ConnectFailed,
+            message: "Reconnect attempts exhausted".to_string(),
+            retryable: false,
+            retry_after_ms: None,
+        }));
+        assert_eq!(
+            received.last(),
+            Some(&SshEvent::Status {
+                state: SshState::Closed
+            })
+        );
+        assert_eq!(session.state(), SshState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_key_credentials_are_rejected_at_start() {
+        let server = crate::test_server::TestServer::start(0).await;
+        let credentials: Arc<dyn CredentialProvider> =
+            Arc::new(|_attempt: u32| -> CredentialFuture {
+                Box::pin(async {
+                    Ok(Credentials::Key {
+                        key: PrivateKeyRef {
+                            pem: SecretString::new("pem".into()),
+                        },
+                        passphrase: None,
+                    })
+                })
+            });
+        let target = ReconnectTarget {
+            host: "127.0.0.1".to_string(),
+            port: server.port(),
+            user: "user".to_string(),
+            options: SessionOptions::default(),
+            host_key_policy: HostKeyPolicy::AcceptNew,
+            known_host: None,
+            pty: Pty::default(),
+            env: Vec::new(),
+        };
+        let Err(e) =
+            ReconnectingSession::connect(target, ReconnectPolicy::default(), credentials).await
+        else {
+            panic!("key credentials accepted");
+        };
+        assert_eq!(e.code, SshErrorCode::BadRequest);
+        assert!(!e.retryable);
+    }
+
+    #[tokio::test]
+    async fn test_connection_info_describes_current_connection() {
+        let server = crate::test_server::TestServer::start(0).await;
+        let credentials: Arc<dyn CredentialProvider> =
+            Arc::new(|_attempt: u32| -> CredentialFuture {
+                Box::pin(async { Ok(Credentials::Password(SecretString::new("pw".into()))) })
+            });
+        let target = ReconnectTarget {
+            host: "127.0.0.1".to_string(),
+            port: server.port(),
+            user: "user".to_string(),
+            options: SessionOptions::default(),
+            host_key_policy: HostKeyPolicy::AcceptNew,
+            known_host: None,
+            pty: Pty::default(),
+            env: Vec::new(),
+        };
+        let session = ReconnectingSession::connect(target, ReconnectPolicy::default(), credentials)
+            .await
+            .unwrap();
+
+        let info = session.connection_info().await.unwrap();
+        assert_eq!(info.remote_addr.port(), server.port());
+        assert!(info.local_addr.ip().is_loopback());
+        assert!(info.server_banner.unwrap().starts_with("SSH-2.0-russh"));
+        assert!(info.algorithms.is_some());
+        assert!(info.auth_latency_ms.is_some());
+
+        session.disconnect().await.unwrap();
+    }
+}
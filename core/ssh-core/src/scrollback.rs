@@ -0,0 +1,193 @@
+//! Scrollback вывода терминала в памяти сессии: для поздних подписчиков, никогда не сохраняется на диск.
+
+use crate::SshEvent;
+use std::collections::VecDeque;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Жёсткий предел для `ScrollbackLimit::Lines`: длинный вывод без переводов строк не растит буфер
+pub const MAX_SCROLLBACK_BYTES: usize = 4 << 20;
+
+/// Ограничение scrollback: по байтам или по строкам (`\n`); `Lines(n)` хранит `n` завершённых
+/// строк плюс текущую незавершённую
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollbackLimit {
+    Bytes(usize),
+    Lines(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub(crate) struct Scrollback {
+    chunks: VecDeque<(Stream, Zeroizing<Vec<u8>>)>,
+    bytes: usize,
+    lines: usize,
+    limit: Option<ScrollbackLimit>,
+}
+
+impl Scrollback {
+    pub(crate) fn new() -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            bytes: 0,
+            lines: 0,
+            limit: None,
+        }
+    }
+
+    /// `None` отключает scrollback и стирает накопленное
+    pub(crate) fn set_limit(&mut self, limit: Option<ScrollbackLimit>) {
+        self.limit = limit;
+        if limit.is_none() {
+            self.clear();
+        } else {
+            self.trim();
+        }
+    }
+
+    pub(crate) fn push(&mut self, event: &SshEvent) {
+        if self.limit.is_none() {
+            return;
+        }
+        let (stream, data) = match event {
+            SshEvent::Stdout { data } => (Stream::Stdout, data),
+            SshEvent::Stderr { data } => (Stream::Stderr, data),
+            _ => return,
+        };
+        if data.is_empty() {
+            return;
+        }
+        self.bytes += data.len();
+        self.lines += count_lines(data);
+        match self.chunks.back_mut() {
+            Some((last, buf)) if *last == stream => append(buf, data),
+            _ => self.chunks.push_back((stream, data.clone())),
+        }
+        self.trim();
+    }
+
+    /// Накопленный вывод в исходном порядке; соседние куски одного потока склеены
+    pub(crate) fn events(&self) -> Vec<SshEvent> {
+        self.chunks
+            .iter()
+            .map(|(stream, data)| match stream {
+                Stream::Stdout => SshEvent::Stdout { data: data.clone() },
+                Stream::Stderr => SshEvent::Stderr { data: data.clone() },
+            })
+            .collect()
+    }
+
+    /// Затирает байты вывода перед освобождением памяти
+    pub(crate) fn clear(&mut self) {
+        self.chunks.clear();
+        self.bytes = 0;
+        self.lines = 0;
+    }
+
+    fn trim(&mut self) {
+        let (max_bytes, max_lines) = match self.limit {
+            None => return,
+            Some(ScrollbackLimit::Bytes(n)) => (n, usize::MAX),
+            Some(ScrollbackLimit::Lines(n)) => (MAX_SCROLLBACK_BYTES, n),
+        };
+        while self.bytes > max_bytes || self.lines > max_lines {
+            let Some((_, front)) = self.chunks.front_mut() else {
+                break;
+            };
+            let cut = if self.bytes > max_bytes {
+                (self.bytes - max_bytes).min(front.len())
+            } else {
+                // Отрезаем по границе самой старой строки
+                front
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(front.len(), |i| i + 1)
+            };
+            self.bytes -= cut;
+            self.lines -= count_lines(&front[..cut]);
+            front[..cut].zeroize();
+            front.drain(..cut);
+            if front.is_empty() {
+                self.chunks.pop_front();
+            }
+        }
+    }
+}
+
+fn count_lines(data: &[u8]) -> usize {
+    data.iter().filter(|&&b| b == b'\n').count()
+}
+
+/// Дописывает в буфер; при росте старая копия затирается, а не остаётся в освобождённой памяти
+pub(crate) fn append(buf: &mut Vec<u8>, data: &[u8]) {
+    if buf.capacity() - buf.len() < data.len() {
+        let mut grown = Vec::with_capacity((buf.len() + data.len()).max(buf.capacity() * 2));
+        grown.extend_from_slice(buf);
+        buf.zeroize();
+        *buf = grown;
+    }
+    buf.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_alloc::{leaked, with_secret};
+
+    #[test]
+    fn test_trimmed_and_cleared_output_leaves_no_copies() {
+        let mut sb = Scrollback::new();
+        sb.set_limit(Some(ScrollbackLimit::Lines(1)));
+        sb.push(&out(&with_secret(b"token=", b"\n")));
+        sb.push(&out(b"next\n"));
+        sb.push(&out(b"more\n"));
+        assert_eq!(sb.events(), vec![out(b"more\n")]);
+
+        sb.push(&out(&with_secret(b"", b"")));
+        sb.set_limit(None);
+        assert_eq!(leaked(), 0);
+    }
+
+    fn out(data: &[u8]) -> SshEvent {
+        SshEvent::Stdout {
+            data: data.to_vec().into(),
+        }
+    }
+
+    #[test]
+    fn test_byte_limit_keeps_tail_and_merges_chunks() {
+        let mut sb = Scrollback::new();
+        sb.set_limit(Some(ScrollbackLimit::Bytes(6)));
+        sb.push(&out(b"abcd"));
+        sb.push(&out(b"efgh"));
+        sb.push(&SshEvent::Stderr {
+            data: b"!".to_vec().into(),
+        });
+        assert_eq!(
+            sb.events(),
+            vec![
+                out(b"defgh"),
+                SshEvent::Stderr {
+                    data: b"!".to_vec().into()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_limit_drops_whole_lines() {
+        let mut sb = Scrollback::new();
+        sb.set_limit(Some(ScrollbackLimit::Lines(2)));
+        sb.push(&out(b"one\ntwo\n"));
+        sb.push(&out(b"three\nfour"));
+        assert_eq!(sb.events(), vec![out(b"two\nthree\nfour")]);
+
+        sb.set_limit(None);
+        assert!(sb.events().is_empty());
+        sb.push(&out(b"ignored"));
+        assert!(sb.events().is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! Открытый канал PTY или exec со стороны `SshSession`: только исходящие запросы.
+//!
+//! Вывод канала приходит через `Handler::data`, но russh 0.49 ещё и копирует каждый пакет
+//! в неограниченную очередь `Channel`. Пока `Channel` жив, эту очередь никто не читал бы,
+//! и весь вывод сессии оставался бы в памяти до закрытия канала. Поэтому `Channel` живёт
+//! в отдельной задаче, которая сразу отбрасывает эти копии и между ними выполняет запросы.
+
+use russh::{client, Channel, ChannelId, Sig};
+use tokio::sync::{mpsc, oneshot};
+
+enum Request {
+    WindowChange {
+        cols: u32,
+        rows: u32,
+        pixel_width: u32,
+        pixel_height: u32,
+    },
+    Signal(Sig),
+    Eof,
+    Close,
+}
+
+type Reply = oneshot::Sender<Result<(), russh::Error>>;
+
+pub(crate) struct SessionChannel {
+    id: ChannelId,
+    requests: mpsc::Sender<(Request, Reply)>,
+}
+
+impl SessionChannel {
+    /// Запросы `pty`, `env`, `shell`/`exec` уже отправлены; задача завершается вместе с `Self`
+    pub(crate) fn spawn(mut channel: Channel<client::Msg>) -> Self {
+        let id = channel.id();
+        let (requests, mut incoming) = mpsc::channel::<(Request, Reply)>(1);
+        tokio::spawn(async move {
+            let mut open = true;
+            loop {
+                tokio::select! {
+                    request = incoming.recv() => {
+                        let Some((request, reply)) = request else {
+                            break;
+                        };
+                        let _ = reply.send(send(&channel, request).await);
+                    }
+                    // Копия того, что уже передано `Handler`; `CryptoVec` затирается при удалении
+                    message = channel.wait(), if open => open = message.is_some(),
+                }
+            }
+        });
+        Self { id, requests }
+    }
+
+    pub(crate) fn id(&self) -> ChannelId {
+        self.id
+    }
+
+    pub(crate) async fn window_change(
+        &self,
+        cols: u32,
+        rows: u32,
+        pixel_width: u32,
+        pixel_height: u32,
+    ) -> Result<(), russh::Error> {
+        self.request(Request::WindowChange {
+            cols,
+            rows,
+            pixel_width,
+            pixel_height,
+        })
+        .await
+    }
+
+    pub(crate) async fn signal(&self, signal: Sig) -> Result<(), russh::Error> {
+        self.request(Request::Signal(signal)).await
+    }
+
+    pub(crate) async fn eof(&self) -> Result<(), russh::Error> {
+        self.request(Request::Eof).await
+    }
+
+    pub(crate) async fn close(&self) -> Result<(), russh::Error> {
+        self.request(Request::Close).await
+    }
+
+    async fn request(&self, request: Request) -> Result<(), russh::Error> {
+        let (reply, result) = oneshot::channel();
+        self.requests
+            .send((request, reply))
+            .await
+            .map_err(|_| russh::Error::SendError)?;
+        result.await.map_err(|_| russh::Error::SendError)?
+    }
+}
+
+async fn send(channel: &Channel<client::Msg>, request: Request) -> Result<(), russh::Error> {
+    match request {
+        Request::WindowChange {
+            cols,
+            rows,
+            pixel_width,
+            pixel_height,
+        } => {
+            channel
+                .window_change(cols, rows, pixel_width, pixel_height)
+                .await
+        }
+        Request::Signal(signal) => channel.signal(signal).await,
+        Request::Eof => channel.eof().await,
+        Request::Close => channel.close().await,
+    }
+}
@@ -0,0 +1,181 @@
+//! Имена сигналов SSH (RFC 4254 §6.10) и их номера.
+
+use crate::SshEvent;
+
+/// Длина сообщения об ошибке из `exit-signal`, которая попадает в событие
+const MAX_ERROR_MESSAGE_CHARS: usize = 512;
+
+const ALL: [Signal; 13] = [
+    Signal::Abrt,
+    Signal::Alrm,
+    Signal::Fpe,
+    Signal::Hup,
+    Signal::Ill,
+    Signal::Int,
+    Signal::Kill,
+    Signal::Pipe,
+    Signal::Quit,
+    Signal::Segv,
+    Signal::Term,
+    Signal::Usr1,
+    Signal::Usr2,
+];
+
+/// Сигналы, которые можно передать удалённому процессу запросом `signal`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Abrt,
+    Alrm,
+    Fpe,
+    Hup,
+    Ill,
+    Int,
+    Kill,
+    Pipe,
+    Quit,
+    Segv,
+    Term,
+    Usr1,
+    Usr2,
+}
+
+impl Signal {
+    /// Имя в любом регистре, с префиксом `SIG` или без
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+        let name = name.strip_prefix("SIG").unwrap_or(&name);
+        ALL.iter().copied().find(|s| s.name() == name)
+    }
+
+    /// Имя без префикса `SIG`, как в протоколе: `INT`, `TERM`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Abrt => "ABRT",
+            Signal::Alrm => "ALRM",
+            Signal::Fpe => "FPE",
+            Signal::Hup => "HUP",
+            Signal::Ill => "ILL",
+            Signal::Int => "INT",
+            Signal::Kill => "KILL",
+            Signal::Pipe => "PIPE",
+            Signal::Quit => "QUIT",
+            Signal::Segv => "SEGV",
+            Signal::Term => "TERM",
+            Signal::Usr1 => "USR1",
+            Signal::Usr2 => "USR2",
+        }
+    }
+
+    /// Номер сигнала в Linux (совпадает с POSIX для всех, кроме `USR1`/`USR2`)
+    pub fn number(&self) -> i32 {
+        match self {
+            Signal::Hup => 1,
+            Signal::Int => 2,
+            Signal::Quit => 3,
+            Signal::Ill => 4,
+            Signal::Abrt => 6,
+            Signal::Fpe => 8,
+            Signal::Kill => 9,
+            Signal::Usr1 => 10,
+            Signal::Segv => 11,
+            Signal::Usr2 => 12,
+            Signal::Pipe => 13,
+            Signal::Alrm => 14,
+            Signal::Term => 15,
+        }
+    }
+
+    fn from_russh(sig: &russh::Sig) -> Option<Self> {
+        Some(match sig {
+            russh::Sig::ABRT => Signal::Abrt,
+            russh::Sig::ALRM => Signal::Alrm,
+            russh::Sig::FPE => Signal::Fpe,
+            russh::Sig::HUP => Signal::Hup,
+            russh::Sig::ILL => Signal::Ill,
+            russh::Sig::INT => Signal::Int,
+            russh::Sig::KILL => Signal::Kill,
+            russh::Sig::PIPE => Signal::Pipe,
+            russh::Sig::QUIT => Signal::Quit,
+            russh::Sig::SEGV => Signal::Segv,
+            russh::Sig::TERM => Signal::Term,
+            russh::Sig::USR1 => Signal::Usr1,
+            russh::Sig::Custom(name) => return Signal::from_name(name),
+        })
+    }
+
+    pub(crate) fn to_russh(self) -> russh::Sig {
+        match self {
+            Signal::Abrt => russh::Sig::ABRT,
+            Signal::Alrm => russh::Sig::ALRM,
+            Signal::Fpe => russh::Sig::FPE,
+            Signal::Hup => russh::Sig::HUP,
+            Signal::Ill => russh::Sig::ILL,
+            Signal::Int => russh::Sig::INT,
+            Signal::Kill => russh::Sig::KILL,
+            Signal::Pipe => russh::Sig::PIPE,
+            Signal::Quit => russh::Sig::QUIT,
+            Signal::Segv => russh::Sig::SEGV,
+            Signal::Term => russh::Sig::TERM,
+            Signal::Usr1 => russh::Sig::USR1,
+            Signal::Usr2 => russh::Sig::Custom("USR2".to_string()),
+        }
+    }
+}
+
+/// `Exit` по `exit-signal`: код `128 + signum` как в оболочке (для неизвестных сигналов — 128),
+/// имя без `SIG`, сообщение сервера без управляющих символов
+pub(crate) fn exit_event(sig: &russh::Sig, core_dumped: bool, error_message: &str) -> SshEvent {
+    let known = Signal::from_russh(sig);
+    let name = match (known, sig) {
+        (Some(signal), _) => signal.name().to_string(),
+        (None, russh::Sig::Custom(name)) => {
+            let name = name.to_ascii_uppercase();
+            name.strip_prefix("SIG").unwrap_or(&name).to_string()
+        }
+        // Стандартные варианты russh всегда сопоставлены; их Debug совпадает с именем
+        (None, other) => format!("{other:?}"),
+    };
+    let message: String = error_message
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_ERROR_MESSAGE_CHARS)
+        .collect();
+    SshEvent::Exit {
+        exit_code: 128 + known.map_or(0, |s| s.number()),
+        signal: Some(name),
+        core_dumped,
+        error_message: (!message.trim().is_empty()).then_some(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_signal_is_normalized() {
+        assert_eq!(
+            exit_event(&russh::Sig::KILL, true, "Killed\x1b[2J\r\n"),
+            SshEvent::Exit {
+                exit_code: 137,
+                signal: Some("KILL".to_string()),
+                core_dumped: true,
+                error_message: Some("Killed[2J".to_string()),
+            }
+        );
+        assert_eq!(
+            exit_event(&russh::Sig::Custom("SIGUSR2".to_string()), false, ""),
+            SshEvent::Exit {
+                exit_code: 140,
+                signal: Some("USR2".to_string()),
+                core_dumped: false,
+                error_message: None,
+            }
+        );
+        assert!(matches!(
+            exit_event(&russh::Sig::Custom("winch".to_string()), false, " "),
+            SshEvent::Exit { exit_code: 128, signal: Some(s), error_message: None, .. } if s == "WINCH"
+        ));
+        assert_eq!(Signal::from_name("sigterm"), Some(Signal::Term));
+    }
+}
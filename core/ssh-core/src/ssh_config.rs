@@ -0,0 +1,1158 @@
+//! Разбор OpenSSH `ssh_config`: `Host`/`Match`/`Include`, first-match-wins и %-токены.
+
+use crate::{AddressFamily, SessionOptions, SshError, SshErrorCode, SshSession};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Предел вложенности `Include` (как `READCONF_MAX_DEPTH` в OpenSSH)
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+const DEFAULT_PORT: u16 = 22;
+
+/// Директивы, значения которых накапливаются, а не берутся из первого совпадения
+const MULTI_VALUE_KEYWORDS: &[&str] = &[
+    "identityfile",
+    "certificatefile",
+    "localforward",
+    "remoteforward",
+    "dynamicforward",
+    "sendenv",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SshConfigError {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl SshConfigError {
+    fn new(path: Option<&Path>, line: usize, message: impl Into<String>) -> Self {
+        Self {
+            path: path.map(Path::to_path_buf),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for SshConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}:{}: {}", path.display(), self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for SshConfigError {}
+
+/// Окружение локального пользователя, от которого зависят `Include`, `~`, `%u` и `%d`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigContext {
+    pub home_dir: PathBuf,
+    pub ssh_dir: PathBuf,
+    pub local_user: String,
+}
+
+impl ConfigContext {
+    pub fn new(home_dir: impl Into<PathBuf>, local_user: impl Into<String>) -> Self {
+        let home_dir = home_dir.into();
+        Self {
+            ssh_dir: home_dir.join(".ssh"),
+            home_dir,
+            local_user: local_user.into(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_default();
+        Self::new(home, user)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrictHostKeyChecking {
+    Yes,
+    No,
+    Ask,
+    AcceptNew,
+}
+
+/// Один переход цепочки `ProxyJump`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JumpHost {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl std::fmt::Display for JumpHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(user) = &self.user {
+            write!(f, "{user}@")?;
+        }
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            f.write_str(&self.host)?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Директива, применённая к хосту, но не разобранная в типизированное поле
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigOption {
+    pub keyword: String,
+    pub args: Vec<String>,
+}
+
+/// Итоговые параметры подключения для одного хоста
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedHost {
+    pub host: String,
+    pub hostname: String,
+    pub port: u16,
+    pub user: String,
+    pub identity_files: Vec<PathBuf>,
+    pub proxy_jump: Vec<JumpHost>,
+    pub proxy_command: Option<String>,
+    pub server_alive_interval: Option<u32>,
+    pub connect_timeout: Option<u32>,
+    pub compression: Option<bool>,
+    pub strict_host_key_checking: Option<StrictHostKeyChecking>,
+    pub user_known_hosts_files: Vec<PathBuf>,
+    pub other: Vec<ConfigOption>,
+}
+
+impl ResolvedHost {
+    /// Подключение по вычисленным параметрам; прокси-цепочки `SshSession` пока не поддерживает
+    pub async fn connect(&self, timeout_ms: u32) -> Result<SshSession, SshError> {
+        if !self.proxy_jump.is_empty() || self.proxy_command.is_some() {
+            return Err(SshError::new(
+                SshErrorCode::BadRequest,
+                "ProxyJump/ProxyCommand are not supported by SshSession::connect",
+                false,
+            ));
+        }
+        SshSession::connect_with(
+            &self.hostname,
+            self.port,
+            &self.user,
+            &self.session_options(timeout_ms),
+        )
+        .await
+    }
+
+    /// `ConnectTimeout` заменяет `timeout_ms`; `ServerAliveInterval 0` выключает keepalive
+    pub fn session_options(&self, timeout_ms: u32) -> SessionOptions {
+        let defaults = SessionOptions::default();
+        let count_max = self
+            .other
+            .iter()
+            .find(|o| o.keyword == "serveralivecountmax")
+            .and_then(|o| o.args.first()?.parse().ok());
+        let address_family = self
+            .other
+            .iter()
+            .find(|o| o.keyword == "addressfamily")
+            .and_then(|o| match o.args.first()?.as_str() {
+                "inet" => Some(AddressFamily::Ipv4Only),
+                "inet6" => Some(AddressFamily::Ipv6Only),
+                _ => None,
+            });
+        SessionOptions {
+            timeout_ms: self
+                .connect_timeout
+                .map_or(timeout_ms, |sec| sec.saturating_mul(1000)),
+            keepalive_interval_ms: self
+                .server_alive_interval
+                .filter(|&sec| sec > 0)
+                .map(|sec| sec.saturating_mul(1000)),
+            keepalive_max: count_max.unwrap_or(defaults.keepalive_max),
+            address_family: address_family.unwrap_or(defaults.address_family),
+            algorithms: defaults
+                .algorithms
+                .with_compression(self.compression.unwrap_or(false)),
+            ..defaults
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Criterion {
+    All,
+    Canonical,
+    Final,
+    Exec,
+    LocalNetwork,
+    Host(String),
+    OriginalHost(String),
+    User(String),
+    LocalUser(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct MatchCriterion {
+    negated: bool,
+    criterion: Criterion,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Directive {
+    keyword: String,
+    args: Vec<String>,
+    path: Option<PathBuf>,
+    line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Line {
+    Host(Vec<String>),
+    Match(Vec<MatchCriterion>),
+    Directive(Directive),
+    Include(Vec<Vec<Line>>),
+}
+
+/// Разобранный `ssh_config` вместе со всеми подключёнными через `Include` файлами
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SshConfig {
+    lines: Vec<Line>,
+    context: ConfigContext,
+}
+
+impl SshConfig {
+    pub fn parse(text: &str, context: ConfigContext) -> Result<Self, SshConfigError> {
+        let lines = parse_lines(text, None, &context, 0)?;
+        Ok(Self { lines, context })
+    }
+
+    pub fn load(path: impl AsRef<Path>, context: ConfigContext) -> Result<Self, SshConfigError> {
+        let lines = load_file(path.as_ref(), &context, 0)?;
+        Ok(Self { lines, context })
+    }
+
+    /// Конкретные хосты из строк `Host` (без шаблонов и отрицаний) в порядке появления
+    pub fn host_aliases(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        collect_aliases(&self.lines, &mut out);
+        out
+    }
+
+    pub fn resolve(&self, host: &str) -> Result<ResolvedHost, SshConfigError> {
+        let mut eval = Evaluator {
+            context: &self.context,
+            original_host: host,
+            final_pass: false,
+            saw_final: false,
+            applied: Vec::new(),
+        };
+        eval.run(&self.lines);
+        if eval.saw_final {
+            eval.final_pass = true;
+            eval.run(&self.lines);
+        }
+        eval.finish()
+    }
+}
+
+fn collect_aliases(lines: &[Line], out: &mut Vec<String>) {
+    for line in lines {
+        match line {
+            Line::Host(patterns) => {
+                for p in patterns {
+                    if !p.starts_with('!') && !p.contains(['*', '?']) && !out.iter().any(|a| a == p)
+                    {
+                        out.push(p.clone());
+                    }
+                }
+            }
+            Line::Include(files) => {
+                for file in files {
+                    collect_aliases(file, out);
+                }
+            }
+            Line::Match(_) | Line::Directive(_) => {}
+        }
+    }
+}
+
+fn load_file(
+    path: &Path,
+    context: &ConfigContext,
+    depth: usize,
+) -> Result<Vec<Line>, SshConfigError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| SshConfigError::new(Some(path), 0, format!("cannot read file: {e}")))?;
+    parse_lines(&text, Some(path), context, depth)
+}
+
+fn parse_lines(
+    text: &str,
+    path: Option<&Path>,
+    context: &ConfigContext,
+    depth: usize,
+) -> Result<Vec<Line>, SshConfigError> {
+    let mut lines = Vec::new();
+    for (idx, raw) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let err = |msg: String| SshConfigError::new(path, line_no, msg);
+
+        let Some((keyword, args)) = split_directive(raw).map_err(err)? else {
+            continue;
+        };
+
+        match keyword.as_str() {
+            "host" => {
+                if args.is_empty() {
+                    return Err(err("Host requires at least one pattern".to_string()));
+                }
+                lines.push(Line::Host(args));
+            }
+            "match" => lines.push(Line::Match(parse_match(&args).map_err(err)?)),
+            "include" => {
+                if args.is_empty() {
+                    return Err(err("Include requires at least one path".to_string()));
+                }
+                if depth + 1 > MAX_INCLUDE_DEPTH {
+                    return Err(err("Include nested too deeply".to_string()));
+                }
+                let mut files = Vec::new();
+                for arg in &args {
+                    let pattern = include_path(arg, context);
+                    for file in expand_glob(&pattern) {
+                        files.push(load_file(&file, context, depth + 1)?);
+                    }
+                }
+                lines.push(Line::Include(files));
+            }
+            _ => {
+                let directive = Directive {
+                    keyword,
+                    args,
+                    path: path.map(Path::to_path_buf),
+                    line: line_no,
+                };
+                validate(&directive)?;
+                lines.push(Line::Directive(directive));
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// Делит строку на ключевое слово (в нижнем регистре) и аргументы с учётом кавычек и `=`
+fn split_directive(raw: &str) -> Result<Option<(String, Vec<String>)>, String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(None);
+    }
+
+    let key_end = trimmed
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(trimmed.len());
+    let keyword = trimmed[..key_end].to_ascii_lowercase();
+    let mut rest = trimmed[key_end..].trim_start();
+    if let Some(stripped) = rest.strip_prefix('=') {
+        rest = stripped.trim_start();
+    }
+
+    let mut args = Vec::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '#' {
+            break;
+        }
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    // Как в OpenSSH: `\"` и `\\` — экранирование, прочие `\` остаются как есть
+                    '\\' if matches!(chars.peek(), Some('"' | '\\')) => {
+                        arg.extend(chars.next());
+                    }
+                    c => arg.push(c),
+                }
+            }
+            if !closed {
+                return Err("unterminated quoted string".to_string());
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+
+    Ok(Some((keyword, args)))
+}
+
+fn parse_match(args: &[String]) -> Result<Vec<MatchCriterion>, String> {
+    if args.is_empty() {
+        return Err("Match requires at least one criterion".to_string());
+    }
+
+    let mut out = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (negated, name) = match arg.strip_prefix('!') {
+            Some(n) => (true, n.to_ascii_lowercase()),
+            None => (false, arg.to_ascii_lowercase()),
+        };
+        let mut value = |name: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("Match {name} requires an argument"))
+        };
+        let criterion = match name.as_str() {
+            "all" => Criterion::All,
+            "canonical" => Criterion::Canonical,
+            "final" => Criterion::Final,
+            "exec" => {
+                value("exec")?;
+                Criterion::Exec
+            }
+            "localnetwork" => {
+                value("localnetwork")?;
+                Criterion::LocalNetwork
+            }
+            "host" => Criterion::Host(value("host")?),
+            "originalhost" => Criterion::OriginalHost(value("originalhost")?),
+            "user" => Criterion::User(value("user")?),
+            "localuser" => Criterion::LocalUser(value("localuser")?),
+            other => return Err(format!("unsupported Match criterion '{other}'")),
+        };
+        out.push(MatchCriterion { negated, criterion });
+    }
+
+    if out.iter().any(|c| c.criterion == Criterion::All)
+        && out.iter().any(|c| {
+            !matches!(
+                c.criterion,
+                Criterion::All | Criterion::Canonical | Criterion::Final
+            )
+        })
+    {
+        return Err("Match all cannot be combined with other criteria".to_string());
+    }
+
+    Ok(out)
+}
+
+fn include_path(arg: &str, context: &ConfigContext) -> PathBuf {
+    if let Some(rest) = arg.strip_prefix("~/") {
+        return context.home_dir.join(rest);
+    }
+    let path = Path::new(arg);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        context.ssh_dir.join(path)
+    }
+}
+
+/// Раскрытие glob (`*`, `?`) по компонентам пути; результат отсортирован, как у glob(3)
+fn expand_glob(pattern: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !part.contains(['*', '?']) {
+            for c in &mut candidates {
+                c.push(component.as_os_str());
+            }
+            continue;
+        }
+
+        let mut next = Vec::new();
+        for dir in &candidates {
+            let Ok(entries) = fs::read_dir(if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir.as_path()
+            }) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.') || part.starts_with('.'))
+                .filter(|name| wildcard_match(&part, name))
+                .collect();
+            names.sort();
+            next.extend(names.into_iter().map(|n| dir.join(n)));
+        }
+        candidates = next;
+    }
+
+    let mut files: Vec<PathBuf> = candidates.into_iter().filter(|p| p.is_file()).collect();
+    files.sort();
+    files
+}
+
+/// Сопоставление с шаблоном OpenSSH (`*` и `?`)
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+/// Список шаблонов через запятую: совпадение с любым позитивным и ни с одним `!`-шаблоном
+fn match_pattern_list(patterns: &[&str], value: &str, case_insensitive: bool) -> bool {
+    let value = if case_insensitive {
+        value.to_ascii_lowercase()
+    } else {
+        value.to_string()
+    };
+    let mut matched = false;
+    for pattern in patterns {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(p) => (true, p),
+            None => (false, *pattern),
+        };
+        let pattern = if case_insensitive {
+            pattern.to_ascii_lowercase()
+        } else {
+            pattern.to_string()
+        };
+        if wildcard_match(&pattern, &value) {
+            if negated {
+                return false;
+            }
+            matched = true;
+        }
+    }
+    matched
+}
+
+struct Evaluator<'a> {
+    context: &'a ConfigContext,
+    original_host: &'a str,
+    final_pass: bool,
+    saw_final: bool,
+    applied: Vec<Directive>,
+}
+
+impl Evaluator<'_> {
+    fn run(&mut self, lines: &[Line]) {
+        let mut active = true;
+        self.run_file(lines, &mut active);
+    }
+
+    fn run_file(&mut self, lines: &[Line], active: &mut bool) {
+        for line in lines {
+            match line {
+                Line::Host(patterns) => {
+                    let patterns: Vec<&str> = patterns.iter().map(String::as_str).collect();
+                    *active = match_pattern_list(&patterns, self.original_host, true);
+                }
+                Line::Match(criteria) => *active = self.match_criteria(criteria),
+                Line::Directive(d) => {
+                    if *active {
+                        self.apply(d);
+                    }
+                }
+                Line::Include(files) => {
+                    if !*active {
+                        continue;
+                    }
+                    for file in files {
+                        // Каждый подключаемый файл начинается как «глобальный» блок
+                        let mut file_active = true;
+                        self.run_file(file, &mut file_active);
+                    }
+                }
+            }
+        }
+    }
+
+    fn value(&self, keyword: &str) -> Option<&[String]> {
+        self.applied
+            .iter()
+            .find(|d| d.keyword == keyword)
+            .map(|d| d.args.as_slice())
+    }
+
+    fn current_hostname(&self) -> String {
+        match self.value("hostname").and_then(|v| v.first()) {
+            // В `HostName` допустимы только `%h` и `%%`; остальное остаётся как есть
+            Some(h) => expand_tokens(h, |token| match token {
+                'h' => Some(self.original_host.to_string()),
+                '%' => Some("%".to_string()),
+                other => Some(format!("%{other}")),
+            })
+            .unwrap_or_else(|_| h.clone()),
+            None => self.original_host.to_string(),
+        }
+    }
+
+    fn current_user(&self) -> String {
+        self.value("user")
+            .and_then(|v| v.first())
+            .cloned()
+            .unwrap_or_else(|| self.context.local_user.clone())
+    }
+
+    fn match_criteria(&mut self, criteria: &[MatchCriterion]) -> bool {
+        let mut result = true;
+        for c in criteria {
+            let matched = match &c.criterion {
+                Criterion::All => true,
+                Criterion::Canonical => self.final_pass,
+                Criterion::Final => {
+                    self.saw_final = true;
+                    self.final_pass
+                }
+                // `exec` намеренно не выполняется, `localnetwork` не поддерживается
+                Criterion::Exec | Criterion::LocalNetwork => false,
+                Criterion::Host(list) => {
+                    let patterns: Vec<&str> = list.split(',').collect();
+                    match_pattern_list(&patterns, &self.current_hostname(), true)
+                }
+                Criterion::OriginalHost(list) => {
+                    let patterns: Vec<&str> = list.split(',').collect();
+                    match_pattern_list(&patterns, self.original_host, true)
+                }
+                Criterion::User(list) => {
+                    let patterns: Vec<&str> = list.split(',').collect();
+                    match_pattern_list(&patterns, &self.current_user(), false)
+                }
+                Criterion::LocalUser(list) => {
+                    let patterns: Vec<&str> = list.split(',').collect();
+                    match_pattern_list(&patterns, &self.context.local_user, false)
+                }
+            };
+            if matched == c.negated {
+                result = false;
+            }
+        }
+        result
+    }
+
+    fn apply(&mut self, d: &Directive) {
+        if MULTI_VALUE_KEYWORDS.contains(&d.keyword.as_str()) || self.value(&d.keyword).is_none() {
+            self.applied.push(d.clone());
+        }
+    }
+
+    fn finish(self) -> Result<ResolvedHost, SshConfigError> {
+        let hostname = self.current_hostname();
+        let port = match self.value("port").and_then(|v| v.first()) {
+            Some(p) => parse_port(p).unwrap_or(DEFAULT_PORT),
+            None => DEFAULT_PORT,
+        };
+        let user = self.current_user();
+        let tokens = Tokens {
+            original_host: self.original_host,
+            hostname: &hostname,
+            port,
+            remote_user: &user,
+            context: self.context,
+        };
+
+        let mut resolved = ResolvedHost {
+            host: self.original_host.to_string(),
+            hostname: hostname.clone(),
+            port,
+            user: user.clone(),
+            identity_files: Vec::new(),
+            proxy_jump: Vec::new(),
+            proxy_command: None,
+            server_alive_interval: None,
+            connect_timeout: None,
+            compression: None,
+            strict_host_key_checking: None,
+            user_known_hosts_files: Vec::new(),
+            other: Vec::new(),
+        };
+
+        for d in &self.applied {
+            let (keyword, args) = (&d.keyword, &d.args);
+            let first = args[0].as_str();
+            match keyword.as_str() {
+                "hostname" | "port" | "user" => {}
+                "identityfile" => {
+                    if !first.eq_ignore_ascii_case("none") {
+                        resolved.identity_files.push(tokens.expand_path(
+                            first,
+                            IDENTITY_TOKENS,
+                            d,
+                        )?);
+                    }
+                }
+                "proxyjump" => {
+                    if !first.eq_ignore_ascii_case("none") {
+                        resolved.proxy_jump = parse_proxy_jump(first).map_err(|m| {
+                            SshConfigError::new(
+                                d.path.as_deref(),
+                                d.line,
+                                format!("ProxyJump: {m}"),
+                            )
+                        })?;
+                    }
+                }
+                "proxycommand" => {
+                    let cmd = args.join(" ");
+                    if !cmd.eq_ignore_ascii_case("none") {
+                        resolved.proxy_command = Some(tokens.expand(&cmd, "hnpr%", d)?);
+                    }
+                }
+                "serveraliveinterval" => resolved.server_alive_interval = first.parse().ok(),
+                "connecttimeout" => resolved.connect_timeout = first.parse().ok(),
+                "compression" => resolved.compression = parse_yes_no(first),
+                "stricthostkeychecking" => {
+                    resolved.strict_host_key_checking = parse_strict_host_key_checking(first)
+                }
+                "userknownhostsfile" => {
+                    for file in args {
+                        if !file.eq_ignore_ascii_case("none") {
+                            resolved.user_known_hosts_files.push(tokens.expand_path(
+                                file,
+                                IDENTITY_TOKENS,
+                                d,
+                            )?);
+                        }
+                    }
+                }
+                _ => resolved.other.push(ConfigOption {
+                    keyword: keyword.clone(),
+                    args: args.clone(),
+                }),
+            }
+        }
+
+        Ok(resolved)
+    }
+}
+
+fn validate(d: &Directive) -> Result<(), SshConfigError> {
+    let Some(first) = d.args.first().map(String::as_str) else {
+        return Err(SshConfigError::new(
+            d.path.as_deref(),
+            d.line,
+            format!("missing argument for '{}'", d.keyword),
+        ));
+    };
+    let bad = |what: &str| {
+        Err(SshConfigError::new(
+            d.path.as_deref(),
+            d.line,
+            format!("invalid {what} '{first}'"),
+        ))
+    };
+    match d.keyword.as_str() {
+        "port" if parse_port(first).is_none() => bad("port"),
+        "serveraliveinterval" | "connecttimeout" if first.parse::<u32>().is_err() => bad("number"),
+        "compression" if parse_yes_no(first).is_none() => bad("yes/no value"),
+        "stricthostkeychecking" if parse_strict_host_key_checking(first).is_none() => {
+            bad("StrictHostKeyChecking value")
+        }
+        "proxyjump" if !first.eq_ignore_ascii_case("none") => match parse_proxy_jump(first) {
+            Ok(_) => Ok(()),
+            Err(m) => Err(SshConfigError::new(d.path.as_deref(), d.line, m)),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn parse_port(s: &str) -> Option<u16> {
+    s.parse::<u16>().ok().filter(|p| *p != 0)
+}
+
+fn parse_yes_no(s: &str) -> Option<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_strict_host_key_checking(s: &str) -> Option<StrictHostKeyChecking> {
+    match s.to_ascii_lowercase().as_str() {
+        "yes" => Some(StrictHostKeyChecking::Yes),
+        "no" | "off" => Some(StrictHostKeyChecking::No),
+        "ask" => Some(StrictHostKeyChecking::Ask),
+        "accept-new" => Some(StrictHostKeyChecking::AcceptNew),
+        _ => None,
+    }
+}
+
+/// `[user@]host[:port]` или `ssh://[user@]host[:port]`, через запятую
+pub(crate) fn parse_proxy_jump(value: &str) -> Result<Vec<JumpHost>, String> {
+    value
+        .split(',')
+        .map(|hop| {
+            let hop = hop.strip_prefix("ssh://").unwrap_or(hop);
+            let (user, rest) = match hop.rsplit_once('@') {
+                Some((u, r)) if !u.is_empty() => (Some(u.to_string()), r),
+                Some(_) => return Err(format!("empty user in '{hop}'")),
+                None => (None, hop),
+            };
+            let (host, port) = if let Some(inner) = rest.strip_prefix('[') {
+                let (host, tail) = inner
+                    .split_once(']')
+                    .ok_or_else(|| format!("unterminated IPv6 address in '{hop}'"))?;
+                match tail.strip_prefix(':') {
+                    Some(p) => (host, Some(p)),
+                    None if tail.is_empty() => (host, None),
+                    None => return Err(format!("invalid jump host '{hop}'")),
+                }
+            } else {
+                match rest.split_once(':') {
+                    Some((h, p)) => (h, Some(p)),
+                    None => (rest, None),
+                }
+            };
+            if host.is_empty() {
+                return Err(format!("empty host in '{hop}'"));
+            }
+            let port = match port {
+                Some(p) => Some(parse_port(p).ok_or_else(|| format!("invalid port in '{hop}'"))?),
+                None => None,
+            };
+            Ok(JumpHost {
+                user,
+                host: host.to_string(),
+                port,
+            })
+        })
+        .collect()
+}
+
+/// Токены `IdentityFile` и `UserKnownHostsFile`
+const IDENTITY_TOKENS: &str = "CdhiLlnpru%";
+
+/// Локальное имя машины (`%l`, `%L`), uid (`%i`) и хеш соединения (`%C`): сведений для них нет
+/// в `ConfigContext`, поэтому они не раскрываются, а передаются дальше без изменений
+const PASS_THROUGH_TOKENS: &str = "CiLl";
+
+/// Раскрытие за один проход слева направо, так что `%%h` даёт `%h`, а не имя хоста.
+/// `lookup` возвращает `None` для неподдерживаемого токена
+fn expand_tokens(value: &str, lookup: impl Fn(char) -> Option<String>) -> Result<String, String> {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let token = chars
+            .next()
+            .ok_or_else(|| format!("trailing '%' in '{value}'"))?;
+        let expanded =
+            lookup(token).ok_or_else(|| format!("unsupported token '%{token}' in '{value}'"))?;
+        out.push_str(&expanded);
+    }
+    Ok(out)
+}
+
+struct Tokens<'a> {
+    original_host: &'a str,
+    hostname: &'a str,
+    port: u16,
+    remote_user: &'a str,
+    context: &'a ConfigContext,
+}
+
+impl Tokens<'_> {
+    /// Разрешённые токены из `PASS_THROUGH_TOKENS` остаются в значении как есть
+    fn expand(&self, value: &str, allowed: &str, d: &Directive) -> Result<String, SshConfigError> {
+        expand_tokens(value, |token| {
+            if PASS_THROUGH_TOKENS.contains(token) && allowed.contains(token) {
+                return Some(format!("%{token}"));
+            }
+            if !allowed.contains(token) {
+                return None;
+            }
+            Some(match token {
+                '%' => "%".to_string(),
+                'h' => self.hostname.to_string(),
+                'n' => self.original_host.to_string(),
+                'p' => self.port.to_string(),
+                'r' => self.remote_user.to_string(),
+                'u' => self.context.local_user.clone(),
+                'd' => self.context.home_dir.to_string_lossy().into_owned(),
+                _ => return None,
+            })
+        })
+        .map_err(|m| SshConfigError::new(d.path.as_deref(), d.line, m))
+    }
+
+    fn expand_path(
+        &self,
+        value: &str,
+        allowed: &str,
+        d: &Directive,
+    ) -> Result<PathBuf, SshConfigError> {
+        let expanded = self.expand(value, allowed, d)?;
+        if expanded == "~" {
+            return Ok(self.context.home_dir.clone());
+        }
+        Ok(match expanded.strip_prefix("~/") {
+            Some(rest) => self.context.home_dir.join(rest),
+            None => PathBuf::from(expanded),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn ctx() -> ConfigContext {
+        ConfigContext::new("/home/alice", "alice")
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock")
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("ssh-config-{name}-{nanos}"));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_first_match_wins_and_defaults() {
+        let cfg = SshConfig::parse(
+            "Host web\n  HostName web.example.com\n  Port 2222\n\
+             Host *\n  User ops\n  Port 22\n  ServerAliveInterval 30\n  AddressFamily inet\n",
+            ctx(),
+        )
+        .unwrap();
+
+        let web = cfg.resolve("web").unwrap();
+        assert_eq!(web.hostname, "web.example.com");
+        assert_eq!(web.port, 2222);
+        assert_eq!(web.user, "ops");
+        assert_eq!(web.server_alive_interval, Some(30));
+        let options = web.session_options(5_000);
+        assert_eq!(options.keepalive_interval_ms, Some(30_000));
+        assert_eq!(options.timeout_ms, 5_000);
+        assert_eq!(options.address_family, AddressFamily::Ipv4Only);
+
+        let other = cfg.resolve("db").unwrap();
+        assert_eq!(other.hostname, "db");
+        assert_eq!(other.port, 22);
+    }
+
+    #[test]
+    fn test_host_pattern_negation() {
+        let cfg = SshConfig::parse(
+            "Host *.example.com !bastion.example.com\n  User app\nHost *\n  User root\n",
+            ctx(),
+        )
+        .unwrap();
+
+        assert_eq!(cfg.resolve("a.example.com").unwrap().user, "app");
+        assert_eq!(cfg.resolve("bastion.example.com").unwrap().user, "root");
+        assert_eq!(cfg.resolve("A.EXAMPLE.COM").unwrap().user, "app");
+    }
+
+    #[test]
+    fn test_match_criteria() {
+        let cfg = SshConfig::parse(
+            "Host prod\n  HostName 10.0.0.5\n\
+             Match host 10.0.0.* user deploy\n  Port 2200\n\
+             Match originalhost prod !localuser alice\n  Port 2201\n\
+             Match exec \"true\"\n  Port 2202\n\
+             Match host 10.0.0.*\n  User deploy\n\
+             Match final host 10.0.0.5\n  IdentityFile ~/.ssh/final_%r\n",
+            ctx(),
+        )
+        .unwrap();
+
+        let r = cfg.resolve("prod").unwrap();
+        // `Match final` запускает второй проход: User уже deploy → срабатывает Port 2200
+        assert_eq!(r.port, 2200);
+        assert_eq!(r.user, "deploy");
+        assert_eq!(
+            r.identity_files,
+            vec![PathBuf::from("/home/alice/.ssh/final_deploy")]
+        );
+    }
+
+    #[test]
+    fn test_token_expansion() {
+        let cfg = SshConfig::parse(
+            "Host gw\n  HostName %h.corp\n  User bob\n  Port 2022\n\
+             IdentityFile %d/keys/%u-%r@%h:%p\n\
+             UserKnownHostsFile ~/.ssh/known_%h %d/shared\n\
+             ProxyCommand nc %h %p\n",
+            ctx(),
+        )
+        .unwrap();
+
+        let r = cfg.resolve("gw").unwrap();
+        assert_eq!(r.hostname, "gw.corp");
+        assert_eq!(
+            r.identity_files,
+            vec![PathBuf::from("/home/alice/keys/alice-bob@gw.corp:2022")]
+        );
+        assert_eq!(
+            r.user_known_hosts_files,
+            vec![
+                PathBuf::from("/home/alice/.ssh/known_gw.corp"),
+                PathBuf::from("/home/alice/shared"),
+            ]
+        );
+        assert_eq!(r.proxy_command.as_deref(), Some("nc gw.corp 2022"));
+
+        let bad = SshConfig::parse("Host *\n  IdentityFile ~/.ssh/%x\n", ctx()).unwrap();
+        assert_eq!(bad.resolve("any").unwrap_err().line, 2);
+
+        let cfg = SshConfig::parse(
+            "Host lit\n  HostName %%h.%h\n  IdentityFile ~/.ssh/id_%C_%l_%L_%i_%%h\n",
+            ctx(),
+        )
+        .unwrap();
+        let r = cfg.resolve("lit").unwrap();
+        assert_eq!(r.hostname, "%h.lit");
+        assert_eq!(
+            r.identity_files,
+            vec![PathBuf::from("/home/alice/.ssh/id_%C_%l_%L_%i_%h")]
+        );
+    }
+
+    #[test]
+    fn test_proxy_jump_and_host_key_checking() {
+        let cfg = SshConfig::parse(
+            "Host inner\n  ProxyJump ops@bastion:2222,[fd00::1]:22\n  \
+             StrictHostKeyChecking accept-new\n  Compression yes\n  ForwardAgent yes\n",
+            ctx(),
+        )
+        .unwrap();
+
+        let r = cfg.resolve("inner").unwrap();
+        assert_eq!(
+            r.proxy_jump,
+            vec![
+                JumpHost {
+                    user: Some("ops".to_string()),
+                    host: "bastion".to_string(),
+                    port: Some(2222),
+                },
+                JumpHost {
+                    user: None,
+                    host: "fd00::1".to_string(),
+                    port: Some(22),
+                },
+            ]
+        );
+        assert_eq!(
+            r.strict_host_key_checking,
+            Some(StrictHostKeyChecking::AcceptNew)
+        );
+        assert_eq!(r.compression, Some(true));
+        assert_eq!(
+            r.other,
+            vec![ConfigOption {
+                keyword: "forwardagent".to_string(),
+                args: vec!["yes".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_carry_line_numbers() {
+        let err = SshConfig::parse("Host a\n  Port nope\n", ctx()).unwrap_err();
+        assert_eq!(err.line, 2);
+
+        let err = SshConfig::parse("Match tagged x\n", ctx()).unwrap_err();
+        assert_eq!(err.line, 1);
+
+        let err = SshConfig::parse("Host a\n  ProxyCommand \"nc %h\n", ctx()).unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn test_include_with_glob_and_block_scope() {
+        let home = temp_dir("include");
+        let ssh_dir = home.join(".ssh");
+        fs::create_dir_all(ssh_dir.join("conf.d")).unwrap();
+        fs::write(
+            ssh_dir.join("conf.d/10-web.conf"),
+            "Host web\n  HostName web.internal\n",
+        )
+        .unwrap();
+        fs::write(ssh_dir.join("conf.d/20-all.conf"), "User included\n").unwrap();
+        fs::write(ssh_dir.join("conf.d/README"), "Port 1\n").unwrap();
+        fs::write(ssh_dir.join("scoped.conf"), "Port 2200\n").unwrap();
+
+        let context = ConfigContext::new(&home, "alice");
+        let cfg = SshConfig::parse(
+            "Host db\n  Include scoped.conf\nHost *\n  Include conf.d/*.conf\n",
+            context,
+        )
+        .unwrap();
+
+        let web = cfg.resolve("web").unwrap();
+        assert_eq!(web.hostname, "web.internal");
+        assert_eq!(web.user, "included");
+        assert_eq!(web.port, 22);
+
+        let db = cfg.resolve("db").unwrap();
+        assert_eq!(db.port, 2200);
+        assert_eq!(db.user, "included");
+
+        assert_eq!(
+            cfg.host_aliases(),
+            vec!["db".to_string(), "web".to_string()]
+        );
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejects_proxy_chains() {
+        let cfg = SshConfig::parse("Host inner\n  ProxyJump bastion\n", ctx()).unwrap();
+        let r = cfg.resolve("inner").unwrap();
+        assert!(matches!(r.connect(1000).await, Err(e) if e.code == SshErrorCode::BadRequest));
+    }
+}
@@ -0,0 +1,1251 @@
+//! Эмулятор терминала VT/xterm без UI: сетка экрана, курсор, атрибуты, альтернативный экран,
+//! области прокрутки, широкие символы и scrollback.
+//!
+//! Вход — байты `SshEvent::Stdout`/`Stderr`, выход — изменённые участки строк для отрисовки
+//! через FFI ([`Terminal::take_diff`]). Комбинируемые символы нулевой ширины не хранятся.
+
+mod search;
+
+use crate::SshEvent;
+pub use search::{Position, SearchMatch, SearchOptions, TextFormat};
+use std::collections::VecDeque;
+use unicode_width::UnicodeWidthChar;
+use zeroize::Zeroize;
+
+/// Сколько строк scrollback хранится по умолчанию
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
+
+/// Биты `Cell::flags`
+pub mod flags {
+    pub const BOLD: u16 = 1 << 0;
+    pub const DIM: u16 = 1 << 1;
+    pub const ITALIC: u16 = 1 << 2;
+    pub const UNDERLINE: u16 = 1 << 3;
+    pub const BLINK: u16 = 1 << 4;
+    pub const INVERSE: u16 = 1 << 5;
+    pub const HIDDEN: u16 = 1 << 6;
+    pub const STRIKETHROUGH: u16 = 1 << 7;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Default,
+    /// Палитра xterm: 0–15 — базовые цвета, 16–255 — куб 6×6×6 и оттенки серого
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Ячейка экрана. У широкого символа `width == 2`, следующая за ним ячейка — заглушка с `width == 0`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub width: u8,
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: u16,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            width: 1,
+            fg: Color::Default,
+            bg: Color::Default,
+            flags: 0,
+        }
+    }
+}
+
+impl Zeroize for Cell {
+    fn zeroize(&mut self) {
+        self.ch.zeroize();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CursorState {
+    pub row: u16,
+    pub col: u16,
+    pub visible: bool,
+}
+
+/// Изменённый участок строки экрана: ячейки начиная с `start_col`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RowUpdate {
+    pub row: u16,
+    pub start_col: u16,
+    pub cells: Vec<Cell>,
+}
+
+/// Изменения с прошлого [`Terminal::take_diff`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScreenDiff {
+    pub rows: Vec<RowUpdate>,
+    pub cursor: CursorState,
+    /// Новый заголовок окна (OSC 0/2), если менялся
+    pub title: Option<String>,
+    pub bell: bool,
+    pub alternate_screen: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Row {
+    pub(crate) cells: Vec<Cell>,
+    /// Строка продолжается на следующей (автоперенос), а не закончилась переводом строки
+    pub(crate) wrapped: bool,
+}
+
+impl Row {
+    fn new(cols: usize, blank: Cell) -> Self {
+        Self {
+            cells: vec![blank; cols],
+            wrapped: false,
+        }
+    }
+}
+
+/// Вывод на экране и в scrollback затирается, когда строка вытесняется или терминал удаляется
+impl Drop for Row {
+    fn drop(&mut self) {
+        self.cells.zeroize();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Pen {
+    fg: Color,
+    bg: Color,
+    flags: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Charset {
+    Ascii,
+    /// DEC Special Graphics: псевдографика `tmux`, `mc`, `dialog`
+    LineDrawing,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    pen: Pen,
+    origin: bool,
+    charsets: [Charset; 2],
+    active_charset: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Modes {
+    autowrap: bool,
+    origin: bool,
+    insert: bool,
+    cursor_visible: bool,
+    application_cursor: bool,
+    bracketed_paste: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            autowrap: true,
+            origin: false,
+            insert: false,
+            cursor_visible: true,
+            application_cursor: false,
+            bracketed_paste: false,
+        }
+    }
+}
+
+/// Состояние экрана; отделено от парсера, потому что `vte::Parser::advance` берёт его по `&mut`
+pub(crate) struct Screen {
+    cols: usize,
+    rows: usize,
+    pub(crate) grid: Vec<Row>,
+    /// Основной экран, пока активен альтернативный
+    primary: Option<Vec<Row>>,
+    pub(crate) scrollback: VecDeque<Row>,
+    scrollback_limit: usize,
+    row: usize,
+    col: usize,
+    /// Символ записан в последнюю колонку; перенос случится при следующем символе (как в xterm)
+    wrap_pending: bool,
+    pen: Pen,
+    saved: Option<SavedCursor>,
+    saved_alternate: Option<SavedCursor>,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    modes: Modes,
+    tabs: Vec<bool>,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    title: Option<String>,
+    title_changed: bool,
+    /// Последний напечатанный символ для REP
+    last_char: Option<char>,
+    bell: bool,
+    dirty: Vec<Option<(usize, usize)>>,
+    responses: Vec<u8>,
+}
+
+/// Эмулятор терминала
+pub struct Terminal {
+    parser: vte::Parser,
+    screen: Screen,
+}
+
+impl Terminal {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            parser: vte::Parser::new(),
+            screen: Screen::new(cols.max(1).into(), rows.max(1).into()),
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.parser.advance(&mut self.screen, byte);
+        }
+    }
+
+    /// Вывод сессии; остальные события игнорируются
+    pub fn feed_event(&mut self, event: &SshEvent) {
+        if let SshEvent::Stdout { data } | SshEvent::Stderr { data } = event {
+            self.feed(data);
+        }
+    }
+
+    /// Без переразбиения строк: лишние колонки отрезаются, лишние верхние строки уходят в scrollback
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.screen.resize(cols.max(1).into(), rows.max(1).into());
+    }
+
+    pub fn cols(&self) -> u16 {
+        self.screen.cols as u16
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.screen.rows as u16
+    }
+
+    pub fn cursor(&self) -> CursorState {
+        self.screen.cursor()
+    }
+
+    pub fn cell(&self, row: u16, col: u16) -> Option<Cell> {
+        self.screen_row(row)?.get(usize::from(col)).copied()
+    }
+
+    pub fn screen_row(&self, row: u16) -> Option<&[Cell]> {
+        self.screen
+            .grid
+            .get(usize::from(row))
+            .map(|r| r.cells.as_slice())
+    }
+
+    /// Строка scrollback; 0 — самая старая
+    pub fn scrollback_row(&self, index: usize) -> Option<&[Cell]> {
+        self.screen
+            .scrollback
+            .get(index)
+            .map(|r| r.cells.as_slice())
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.screen.scrollback.len()
+    }
+
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        self.screen.scrollback_limit = lines;
+        self.screen.trim_scrollback();
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.screen.primary.is_some()
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.screen.title.as_deref()
+    }
+
+    /// Включён ли режим DECCKM: стрелки надо слать как `ESC O A`, а не `ESC [ A`
+    pub fn application_cursor(&self) -> bool {
+        self.screen.modes.application_cursor
+    }
+
+    pub fn bracketed_paste(&self) -> bool {
+        self.screen.modes.bracketed_paste
+    }
+
+    /// Текст видимой строки без хвостовых пробелов
+    pub fn row_text(&self, row: u16) -> Option<String> {
+        self.screen_row(row).map(cells_text)
+    }
+
+    /// Ответы терминала (DSR, DA), которые нужно отправить серверу через `write_stdin`
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.screen.responses)
+    }
+
+    /// Изменённые ячейки с прошлого вызова; после изменения размера или переключения экрана — все строки
+    pub fn take_diff(&mut self) -> ScreenDiff {
+        self.screen.take_diff()
+    }
+}
+
+/// Текст ячеек без заглушек широких символов и хвостовых пробелов
+pub(crate) fn cells_text(cells: &[Cell]) -> String {
+    let text: String = cells.iter().filter(|c| c.width > 0).map(|c| c.ch).collect();
+    text.trim_end_matches(' ').to_string()
+}
+
+impl Screen {
+    fn new(cols: usize, rows: usize) -> Self {
+        let mut screen = Self {
+            cols,
+            rows,
+            grid: (0..rows).map(|_| Row::new(cols, Cell::default())).collect(),
+            primary: None,
+            scrollback: VecDeque::new(),
+            scrollback_limit: DEFAULT_SCROLLBACK_LINES,
+            row: 0,
+            col: 0,
+            wrap_pending: false,
+            pen: Pen::default(),
+            saved: None,
+            saved_alternate: None,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            modes: Modes::default(),
+            tabs: Vec::new(),
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            title: None,
+            title_changed: false,
+            last_char: None,
+            bell: false,
+            dirty: vec![None; rows],
+            responses: Vec::new(),
+        };
+        screen.reset_tabs();
+        screen.mark_all();
+        screen
+    }
+
+    fn cursor(&self) -> CursorState {
+        CursorState {
+            row: self.row as u16,
+            col: self.col as u16,
+            visible: self.modes.cursor_visible,
+        }
+    }
+
+    fn blank(&self) -> Cell {
+        Cell {
+            bg: self.pen.bg,
+            ..Cell::default()
+        }
+    }
+
+    fn reset_tabs(&mut self) {
+        self.tabs = (0..self.cols).map(|c| c % 8 == 0 && c > 0).collect();
+    }
+
+    fn mark(&mut self, row: usize, start: usize, end: usize) {
+        let end = end.min(self.cols);
+        if row >= self.rows || start >= end {
+            return;
+        }
+        let span = match self.dirty[row] {
+            Some((s, e)) => (s.min(start), e.max(end)),
+            None => (start, end),
+        };
+        self.dirty[row] = Some(span);
+    }
+
+    fn mark_rows(&mut self, from: usize, to: usize) {
+        for row in from..=to.min(self.rows - 1) {
+            self.mark(row, 0, self.cols);
+        }
+    }
+
+    fn mark_all(&mut self) {
+        self.mark_rows(0, self.rows - 1);
+    }
+
+    fn take_diff(&mut self) -> ScreenDiff {
+        let mut rows = Vec::new();
+        for (row, span) in self.dirty.iter_mut().enumerate() {
+            if let Some((start, end)) = span.take() {
+                rows.push(RowUpdate {
+                    row: row as u16,
+                    start_col: start as u16,
+                    cells: self.grid[row].cells[start..end].to_vec(),
+                });
+            }
+        }
+        ScreenDiff {
+            rows,
+            cursor: self.cursor(),
+            title: std::mem::take(&mut self.title_changed)
+                .then(|| self.title.clone())
+                .flatten(),
+            bell: std::mem::take(&mut self.bell),
+            alternate_screen: self.primary.is_some(),
+        }
+    }
+
+    fn trim_scrollback(&mut self) {
+        while self.scrollback.len() > self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+    }
+
+    fn push_scrollback(&mut self, row: Row) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        self.scrollback.push_back(row);
+        self.trim_scrollback();
+    }
+
+    // --- Запись символов ---
+
+    fn print_char(&mut self, c: char) {
+        let c = match self.charsets[self.active_charset] {
+            Charset::Ascii => c,
+            Charset::LineDrawing => line_drawing(c),
+        };
+        let width = match c.width() {
+            None | Some(0) => return,
+            Some(1) => 1,
+            Some(_) => 2,
+        };
+        self.last_char = Some(c);
+        if self.wrap_pending && self.modes.autowrap {
+            self.wrap_line();
+        }
+        self.wrap_pending = false;
+        if width == 2 && self.col + 1 >= self.cols {
+            if self.cols < 2 {
+                return;
+            }
+            if self.modes.autowrap {
+                self.wrap_line();
+            } else {
+                self.col = self.cols - 2;
+            }
+        }
+        if self.modes.insert {
+            self.insert_chars(width);
+        }
+        let cell = Cell {
+            ch: c,
+            width: width as u8,
+            fg: self.pen.fg,
+            bg: self.pen.bg,
+            flags: self.pen.flags,
+        };
+        self.put_cell(self.row, self.col, cell);
+        if width == 2 {
+            self.put_cell(
+                self.row,
+                self.col + 1,
+                Cell {
+                    ch: ' ',
+                    width: 0,
+                    ..cell
+                },
+            );
+        }
+        if self.col + width >= self.cols {
+            self.col = self.cols - 1;
+            self.wrap_pending = self.modes.autowrap;
+        } else {
+            self.col += width;
+        }
+    }
+
+    fn wrap_line(&mut self) {
+        self.grid[self.row].wrapped = true;
+        self.col = 0;
+        self.linefeed();
+    }
+
+    /// Запись ячейки с разрушением широкого символа, который она перекрывает наполовину
+    fn put_cell(&mut self, row: usize, col: usize, cell: Cell) {
+        let blank = self.blank();
+        let cells = &mut self.grid[row].cells;
+        match cells[col].width {
+            0 if col > 0 => {
+                cells[col - 1] = blank;
+                self.mark(row, col - 1, col);
+            }
+            2 if col + 1 < self.cols && cell.width != 2 => {
+                self.grid[row].cells[col + 1] = blank;
+                self.mark(row, col + 1, col + 2);
+            }
+            _ => {}
+        }
+        self.grid[row].cells[col] = cell;
+        self.mark(row, col, col + 1);
+    }
+
+    // --- Перемещение курсора и прокрутка ---
+
+    fn linefeed(&mut self) {
+        if self.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.row + 1 < self.rows {
+            self.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        if self.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.row > 0 {
+            self.row -= 1;
+        }
+    }
+
+    /// Строки уходят в scrollback, только если прокручивается верх основного экрана
+    fn scroll_up(&mut self, n: usize) {
+        let n = n.min(self.scroll_bottom - self.scroll_top + 1);
+        let blank = Row::new(self.cols, self.blank());
+        for _ in 0..n {
+            let row = self.grid.remove(self.scroll_top);
+            if self.scroll_top == 0 && self.primary.is_none() {
+                self.push_scrollback(row);
+            }
+            self.grid.insert(self.scroll_bottom, blank.clone());
+        }
+        self.mark_rows(self.scroll_top, self.scroll_bottom);
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.scroll_bottom - self.scroll_top + 1);
+        let blank = Row::new(self.cols, self.blank());
+        for _ in 0..n {
+            self.grid.remove(self.scroll_bottom);
+            self.grid.insert(self.scroll_top, blank.clone());
+        }
+        self.mark_rows(self.scroll_top, self.scroll_bottom);
+    }
+
+    /// CUP с учётом DECOM: в режиме origin строки отсчитываются от верха области прокрутки
+    fn goto(&mut self, row: usize, col: usize) {
+        let (top, bottom) = if self.modes.origin {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        self.row = (top + row).min(bottom);
+        self.col = col.min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn move_rows(&mut self, delta: isize) {
+        // Внутри области прокрутки курсор не выходит за её границы
+        let (top, bottom) = if (self.scroll_top..=self.scroll_bottom).contains(&self.row) {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        self.row = self.row.saturating_add_signed(delta).clamp(top, bottom);
+        self.wrap_pending = false;
+    }
+
+    fn move_cols(&mut self, delta: isize) {
+        self.col = self.col.saturating_add_signed(delta).min(self.cols - 1);
+        self.wrap_pending = false;
+    }
+
+    fn tab(&mut self, forward: bool, count: usize) {
+        for _ in 0..count {
+            if forward {
+                self.col = (self.col + 1..self.cols)
+                    .find(|&c| self.tabs[c])
+                    .unwrap_or(self.cols - 1);
+            } else {
+                self.col = (0..self.col).rev().find(|&c| self.tabs[c]).unwrap_or(0);
+            }
+        }
+        self.wrap_pending = false;
+    }
+
+    // --- Стирание и вставка ---
+
+    fn clear_cells(&mut self, row: usize, from: usize, to: usize) {
+        let blank = self.blank();
+        let to = to.min(self.cols);
+        if from >= to {
+            return;
+        }
+        // Половина широкого символа на границе тоже стирается
+        let from = if from > 0 && self.grid[row].cells[from].width == 0 {
+            from - 1
+        } else {
+            from
+        };
+        let to = if to < self.cols && self.grid[row].cells[to].width == 0 {
+            to + 1
+        } else {
+            to
+        };
+        self.grid[row].cells[from..to].fill(blank);
+        self.mark(row, from, to);
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        match mode {
+            0 => {
+                self.clear_cells(self.row, self.col, self.cols);
+                for row in self.row + 1..self.rows {
+                    self.clear_row(row);
+                }
+            }
+            1 => {
+                for row in 0..self.row {
+                    self.clear_row(row);
+                }
+                self.clear_cells(self.row, 0, self.col + 1);
+            }
+            2 => (0..self.rows).for_each(|row| self.clear_row(row)),
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        self.clear_cells(row, 0, self.cols);
+        self.grid[row].wrapped = false;
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        match mode {
+            0 => self.clear_cells(self.row, self.col, self.cols),
+            1 => self.clear_cells(self.row, 0, self.col + 1),
+            2 => self.clear_cells(self.row, 0, self.cols),
+            _ => {}
+        }
+    }
+
+    fn insert_lines(&mut self, n: usize) {
+        if !(self.scroll_top..=self.scroll_bottom).contains(&self.row) {
+            return;
+        }
+        let top = std::mem::replace(&mut self.scroll_top, self.row);
+        self.scroll_down(n);
+        self.scroll_top = top;
+        self.col = 0;
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        if !(self.scroll_top..=self.scroll_bottom).contains(&self.row) {
+            return;
+        }
+        let top = std::mem::replace(&mut self.scroll_top, self.row);
+        // Удалённые строки из середины экрана в scrollback не попадают
+        let limit = std::mem::replace(&mut self.scrollback_limit, 0);
+        self.scroll_up(n);
+        self.scrollback_limit = limit;
+        self.scroll_top = top;
+        self.col = 0;
+    }
+
+    fn insert_chars(&mut self, n: usize) {
+        let blank = self.blank();
+        let (row, col, cols) = (self.row, self.col, self.cols);
+        let n = n.min(cols - col);
+        let cells = &mut self.grid[row].cells;
+        cells[col..].rotate_right(n);
+        cells[col..col + n].fill(blank);
+        fix_split_wide(cells, blank);
+        self.mark(row, col, cols);
+    }
+
+    fn delete_chars(&mut self, n: usize) {
+        let blank = self.blank();
+        let (row, col, cols) = (self.row, self.col, self.cols);
+        let n = n.min(cols - col);
+        let cells = &mut self.grid[row].cells;
+        cells[col..].rotate_left(n);
+        cells[cols - n..].fill(blank);
+        fix_split_wide(cells, blank);
+        self.mark(row, col, cols);
+    }
+
+    // --- Режимы и экраны ---
+
+    fn save_cursor(&self) -> SavedCursor {
+        SavedCursor {
+            row: self.row,
+            col: self.col,
+            pen: self.pen,
+            origin: self.modes.origin,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        }
+    }
+
+    fn restore_cursor(&mut self, saved: Option<SavedCursor>) {
+        let saved = saved.unwrap_or(SavedCursor {
+            row: 0,
+            col: 0,
+            pen: Pen::default(),
+            origin: false,
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+        });
+        self.row = saved.row.min(self.rows - 1);
+        self.col = saved.col.min(self.cols - 1);
+        self.pen = saved.pen;
+        self.modes.origin = saved.origin;
+        self.charsets = saved.charsets;
+        self.active_charset = saved.active_charset;
+        self.wrap_pending = false;
+    }
+
+    fn enter_alternate(&mut self, save_cursor: bool) {
+        if self.primary.is_some() {
+            return;
+        }
+        if save_cursor {
+            self.saved_alternate = Some(self.save_cursor());
+        }
+        let blank = Row::new(self.cols, self.blank());
+        let alternate = vec![blank; self.rows];
+        self.primary = Some(std::mem::replace(&mut self.grid, alternate));
+        self.mark_all();
+    }
+
+    fn leave_alternate(&mut self, restore_cursor: bool) {
+        let Some(primary) = self.primary.take() else {
+            return;
+        };
+        self.grid = primary;
+        if restore_cursor {
+            let saved = self.saved_alternate.take();
+            self.restore_cursor(saved);
+        }
+        self.mark_all();
+    }
+
+    fn set_private_mode(&mut self, mode: u16, on: bool) {
+        match mode {
+            1 => self.modes.application_cursor = on,
+            6 => {
+                self.modes.origin = on;
+                self.goto(0, 0);
+            }
+            7 => self.modes.autowrap = on,
+            25 => self.modes.cursor_visible = on,
+            47 | 1047 => {
+                if on {
+                    self.enter_alternate(false);
+                } else {
+                    self.leave_alternate(false);
+                }
+            }
+            1048 => {
+                if on {
+                    self.saved = Some(self.save_cursor());
+                } else {
+                    self.restore_cursor(self.saved);
+                }
+            }
+            1049 => {
+                if on {
+                    self.enter_alternate(true);
+                } else {
+                    self.leave_alternate(true);
+                }
+            }
+            2004 => self.modes.bracketed_paste = on,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.leave_alternate(false);
+        self.pen = Pen::default();
+        self.modes = Modes::default();
+        self.charsets = [Charset::Ascii; 2];
+        self.active_charset = 0;
+        self.saved = None;
+        self.saved_alternate = None;
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows - 1;
+        self.reset_tabs();
+        self.erase_display(2);
+        self.goto(0, 0);
+    }
+
+    fn resize(&mut self, cols: usize, rows: usize) {
+        if cols == self.cols && rows == self.rows {
+            return;
+        }
+        let blank = Cell::default();
+        // Лишние строки над курсором уходят в scrollback, остальные отрезаются снизу
+        if rows < self.rows {
+            let shift = (self.row + 1).saturating_sub(rows);
+            for _ in 0..shift {
+                let row = self.grid.remove(0);
+                if self.primary.is_none() {
+                    self.push_scrollback(row);
+                }
+            }
+            self.row -= shift;
+        }
+        for grid in std::iter::once(&mut self.grid).chain(self.primary.as_mut()) {
+            grid.truncate(rows);
+            grid.resize_with(rows, || Row::new(cols, blank));
+            for row in grid.iter_mut() {
+                row.cells.resize(cols, blank);
+                fix_split_wide(&mut row.cells, blank);
+            }
+        }
+        self.cols = cols;
+        self.rows = rows;
+        self.row = self.row.min(rows - 1);
+        self.col = self.col.min(cols - 1);
+        self.wrap_pending = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.reset_tabs();
+        self.dirty = vec![None; rows];
+        self.mark_all();
+    }
+
+    // --- SGR ---
+
+    fn sgr(&mut self, params: &[Vec<u16>]) {
+        if params.is_empty() {
+            self.pen = Pen::default();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            let param = &params[i];
+            let code = param.first().copied().unwrap_or(0);
+            match code {
+                0 => self.pen = Pen::default(),
+                1 => self.pen.flags |= flags::BOLD,
+                2 => self.pen.flags |= flags::DIM,
+                3 => self.pen.flags |= flags::ITALIC,
+                // `4:0` — подчёркивание выключено, остальные стили `4:n` сводятся к обычному
+                4 if param.get(1) == Some(&0) => self.pen.flags &= !flags::UNDERLINE,
+                4 | 21 => self.pen.flags |= flags::UNDERLINE,
+                5 | 6 => self.pen.flags |= flags::BLINK,
+                7 => self.pen.flags |= flags::INVERSE,
+                8 => self.pen.flags |= flags::HIDDEN,
+                9 => self.pen.flags |= flags::STRIKETHROUGH,
+                22 => self.pen.flags &= !(flags::BOLD | flags::DIM),
+                23 => self.pen.flags &= !flags::ITALIC,
+                24 => self.pen.flags &= !flags::UNDERLINE,
+                25 => self.pen.flags &= !flags::BLINK,
+                27 => self.pen.flags &= !flags::INVERSE,
+                28 => self.pen.flags &= !flags::HIDDEN,
+                29 => self.pen.flags &= !flags::STRIKETHROUGH,
+                30..=37 => self.pen.fg = Color::Indexed((code - 30) as u8),
+                39 => self.pen.fg = Color::Default,
+                40..=47 => self.pen.bg = Color::Indexed((code - 40) as u8),
+                49 => self.pen.bg = Color::Default,
+                90..=97 => self.pen.fg = Color::Indexed((code - 90 + 8) as u8),
+                100..=107 => self.pen.bg = Color::Indexed((code - 100 + 8) as u8),
+                38 | 48 => {
+                    let (color, consumed) = extended_color(params, i);
+                    if let Some(color) = color {
+                        if code == 38 {
+                            self.pen.fg = color;
+                        } else {
+                            self.pen.bg = color;
+                        }
+                    }
+                    i += consumed;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn report(&mut self, response: String) {
+        self.responses.extend_from_slice(response.as_bytes());
+    }
+}
+
+/// Цвет `38;5;n`, `38;2;r;g;b` или те же формы через `:`; второе значение — сколько параметров
+/// после текущего занято цветом
+fn extended_color(params: &[Vec<u16>], i: usize) -> (Option<Color>, usize) {
+    let param = &params[i];
+    if param.len() > 1 {
+        // Форма с подпараметрами: `38:2::r:g:b` (с пустым id палитры) или `38:2:r:g:b`
+        let sub = &param[1..];
+        let color = match sub {
+            [5, n, ..] => Some(Color::Indexed(*n as u8)),
+            [2, _, r, g, b, ..] | [2, r, g, b] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+            _ => None,
+        };
+        return (color, 0);
+    }
+    let value = |k: usize| params.get(i + k).and_then(|p| p.first()).map(|&v| v as u8);
+    match value(1) {
+        Some(5) => (value(2).map(Color::Indexed), 2),
+        Some(2) => match (value(2), value(3), value(4)) {
+            (Some(r), Some(g), Some(b)) => (Some(Color::Rgb(r, g, b)), 4),
+            _ => (None, params.len() - i),
+        },
+        _ => (None, 0),
+    }
+}
+
+/// После сдвига ячеек широкий символ мог потерять заглушку или наоборот
+fn fix_split_wide(cells: &mut [Cell], blank: Cell) {
+    for i in 0..cells.len() {
+        let broken = match cells[i].width {
+            0 => i == 0 || cells[i - 1].width != 2,
+            2 => cells.get(i + 1).is_none_or(|next| next.width != 0),
+            _ => false,
+        };
+        if broken {
+            cells[i] = blank;
+        }
+    }
+}
+
+fn line_drawing(c: char) -> char {
+    match c {
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        other => other,
+    }
+}
+
+/// Длина заголовка окна, которую примет эмулятор
+const MAX_TITLE_CHARS: usize = 256;
+
+impl vte::Perform for Screen {
+    fn print(&mut self, c: char) {
+        self.print_char(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x07 => self.bell = true,
+            0x08 => self.move_cols(-1),
+            0x09 => self.tab(true, 1),
+            0x0a..=0x0c => {
+                self.linefeed();
+                self.wrap_pending = false;
+            }
+            0x0d => {
+                self.col = 0;
+                self.wrap_pending = false;
+            }
+            0x0e => self.active_charset = 1,
+            0x0f => self.active_charset = 0,
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        if let [b"0" | b"2", rest @ ..] = params {
+            let title = rest.join(&b';');
+            let title: String = String::from_utf8_lossy(&title)
+                .chars()
+                .filter(|c| !c.is_control())
+                .take(MAX_TITLE_CHARS)
+                .collect();
+            self.title = Some(title);
+            self.title_changed = true;
+        }
+    }
+
+    fn csi_dispatch(
+        &mut self,
+        params: &vte::Params,
+        intermediates: &[u8],
+        ignore: bool,
+        action: char,
+    ) {
+        if ignore {
+            return;
+        }
+        let params: Vec<Vec<u16>> = params.iter().map(<[u16]>::to_vec).collect();
+        let arg = |i: usize, default: u16| -> u16 {
+            match params.get(i).and_then(|p| p.first()) {
+                Some(&0) | None => default,
+                Some(&v) => v,
+            }
+        };
+        let n = usize::from(arg(0, 1));
+        match (intermediates, action) {
+            ([], 'A') => self.move_rows(-(n as isize)),
+            ([], 'B' | 'e') => self.move_rows(n as isize),
+            ([], 'C' | 'a') => self.move_cols(n as isize),
+            ([], 'D') => self.move_cols(-(n as isize)),
+            ([], 'E') => {
+                self.move_rows(n as isize);
+                self.col = 0;
+            }
+            ([], 'F') => {
+                self.move_rows(-(n as isize));
+                self.col = 0;
+            }
+            ([], 'G' | '`') => {
+                self.col = (n - 1).min(self.cols - 1);
+                self.wrap_pending = false;
+            }
+            ([], 'H' | 'f') => self.goto(usize::from(arg(0, 1)) - 1, usize::from(arg(1, 1)) - 1),
+            ([], 'd') => {
+                let col = self.col;
+                self.goto(n - 1, col);
+            }
+            ([], 'I') => self.tab(true, n),
+            ([], 'Z') => self.tab(false, n),
+            ([], 'J') => self.erase_display(arg(0, 0)),
+            ([], 'K') => self.erase_line(arg(0, 0)),
+            ([], 'L') => self.insert_lines(n),
+            ([], 'M') => self.delete_lines(n),
+            ([], '@') => self.insert_chars(n),
+            ([], 'P') => self.delete_chars(n),
+            ([], 'X') => {
+                let (row, col) = (self.row, self.col);
+                self.clear_cells(row, col, col + n);
+            }
+            ([], 'S') => self.scroll_up(n),
+            ([], 'T') => self.scroll_down(n),
+            ([], 'b') => {
+                // REP: повтор последнего символа, не больше одного экрана
+                if let Some(c) = self.last_char {
+                    for _ in 0..n.min(self.cols * self.rows) {
+                        self.print_char(c);
+                    }
+                }
+            }
+            ([], 'g') => match arg(0, 0) {
+                0 => self.tabs[self.col] = false,
+                3 => self.tabs.fill(false),
+                _ => {}
+            },
+            ([], 'm') => self.sgr(&params),
+            ([], 'r') => {
+                let top = usize::from(arg(0, 1)) - 1;
+                let bottom = (usize::from(arg(1, self.rows as u16)) - 1).min(self.rows - 1);
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.goto(0, 0);
+                }
+            }
+            ([], 's') => self.saved = Some(self.save_cursor()),
+            ([], 'u') => self.restore_cursor(self.saved),
+            ([], 'n') => match arg(0, 0) {
+                5 => self.report("\x1b[0n".to_string()),
+                6 => {
+                    let row = if self.modes.origin {
+                        self.row - self.scroll_top
+                    } else {
+                        self.row
+                    };
+                    self.report(format!("\x1b[{};{}R", row + 1, self.col + 1));
+                }
+                _ => {}
+            },
+            ([], 'c') => self.report("\x1b[?1;2c".to_string()),
+            ([b'>'], 'c') => self.report("\x1b[>0;0;0c".to_string()),
+            ([], 'h' | 'l') if params.iter().any(|p| p.first() == Some(&4)) => {
+                self.modes.insert = action == 'h';
+            }
+            ([b'?'], 'h' | 'l') => {
+                for mode in params.iter().filter_map(|p| p.first()) {
+                    self.set_private_mode(*mode, action == 'h');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore {
+            return;
+        }
+        match (intermediates, byte) {
+            ([], b'7') => self.saved = Some(self.save_cursor()),
+            ([], b'8') => self.restore_cursor(self.saved),
+            ([], b'D') => self.linefeed(),
+            ([], b'E') => {
+                self.linefeed();
+                self.col = 0;
+            }
+            ([], b'M') => self.reverse_index(),
+            ([], b'H') => self.tabs[self.col] = true,
+            ([], b'c') => self.reset(),
+            ([b'('], b'0') => self.charsets[0] = Charset::LineDrawing,
+            ([b'('], _) => self.charsets[0] = Charset::Ascii,
+            ([b')'], b'0') => self.charsets[1] = Charset::LineDrawing,
+            ([b')'], _) => self.charsets[1] = Charset::Ascii,
+            _ => {}
+        }
+        self.wrap_pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(cols: u16, rows: u16, input: &str) -> Terminal {
+        let mut t = Terminal::new(cols, rows);
+        t.feed(input.as_bytes());
+        t
+    }
+
+    #[test]
+    fn test_print_wrap_and_cursor_movement() {
+        let t = term(5, 4, "hello world\r\n\x1b[1;3HX");
+        assert_eq!(t.row_text(0).unwrap(), "heXlo");
+        assert_eq!(t.row_text(1).unwrap(), " worl");
+        assert_eq!(t.row_text(2).unwrap(), "d");
+        assert!(t.screen.grid[0].wrapped);
+        assert_eq!(
+            t.cursor(),
+            CursorState {
+                row: 0,
+                col: 3,
+                visible: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_sgr_colors_and_attributes() {
+        let t = term(
+            10,
+            1,
+            "\x1b[1;31ma\x1b[38;5;200;48;2;1;2;3mb\x1b[38:2::9:8:7;4mc\x1b[0md",
+        );
+        let a = t.cell(0, 0).unwrap();
+        assert_eq!((a.fg, a.flags), (Color::Indexed(1), flags::BOLD));
+        let b = t.cell(0, 1).unwrap();
+        assert_eq!((b.fg, b.bg), (Color::Indexed(200), Color::Rgb(1, 2, 3)));
+        let c = t.cell(0, 2).unwrap();
+        assert_eq!(c.fg, Color::Rgb(9, 8, 7));
+        assert_eq!(c.flags, flags::BOLD | flags::UNDERLINE);
+        assert_eq!(
+            t.cell(0, 3).unwrap(),
+            Cell {
+                ch: 'd',
+                ..Cell::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_scrollback_and_scroll_region() {
+        let mut t = term(4, 2, "1\r\n2\r\n3\r\n4");
+        assert_eq!(t.scrollback_len(), 2);
+        assert_eq!(cells_text(t.scrollback_row(0).unwrap()), "1");
+        assert_eq!(t.row_text(0).unwrap(), "3");
+
+        // Прокрутка в области, не начинающейся с верха, scrollback не трогает
+        let mut r = term(4, 4, "a\r\nb\r\nc\r\nd\x1b[2;3r\x1b[3;1H\n\n");
+        assert_eq!(r.scrollback_len(), 0);
+        let rows: Vec<_> = (0..4).map(|i| r.row_text(i).unwrap()).collect();
+        assert_eq!(rows, ["a", "", "", "d"]);
+
+        t.set_scrollback_limit(1);
+        assert_eq!(cells_text(t.scrollback_row(0).unwrap()), "2");
+        r.feed(b"\x1b[3J");
+        assert_eq!(r.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn test_alternate_screen_restores_primary_and_cursor() {
+        let mut t = term(6, 2, "shell\x1b[?1049h\x1b[2J\x1b[1;1Hvim");
+        assert!(t.is_alternate_screen());
+        assert_eq!(t.row_text(0).unwrap(), "vim");
+        t.feed(b"\r\n\r\n\r\n");
+        assert_eq!(t.scrollback_len(), 0);
+        t.feed(b"\x1b[?1049l");
+        assert!(!t.is_alternate_screen());
+        assert_eq!(t.row_text(0).unwrap(), "shell");
+        assert_eq!(t.cursor().col, 5);
+    }
+
+    #[test]
+    fn test_wide_characters_occupy_two_cells() {
+        let mut t = term(5, 2, "ab漢字");
+        assert_eq!(t.cell(0, 2).unwrap().width, 2);
+        assert_eq!(t.cell(0, 3).unwrap().width, 0);
+        // Третья ширина не влезла: `字` перенесён целиком
+        assert_eq!(t.row_text(0).unwrap(), "ab漢");
+        assert_eq!(t.row_text(1).unwrap(), "字");
+
+        // Запись поверх второй половины стирает символ целиком
+        t.feed(b"\x1b[1;4Hx");
+        assert_eq!(t.row_text(0).unwrap(), "ab x");
+    }
+
+    #[test]
+    fn test_diff_reports_changed_spans_and_responses() {
+        let mut t = Terminal::new(10, 3);
+        let full = t.take_diff();
+        assert_eq!(full.rows.len(), 3);
+
+        t.feed(b"\x1b[2;4Hab\x1b]2;build\x07\x07\x1b[6n");
+        let diff = t.take_diff();
+        assert_eq!(diff.rows.len(), 1);
+        assert_eq!((diff.rows[0].row, diff.rows[0].start_col), (1, 3));
+        assert_eq!(diff.rows[0].cells.len(), 2);
+        assert_eq!(diff.title.as_deref(), Some("build"));
+        assert!(diff.bell);
+        assert_eq!(t.take_responses(), b"\x1b[2;6R");
+        assert!(t.take_diff().rows.is_empty());
+    }
+
+    #[test]
+    fn test_resize_keeps_cursor_line_visible() {
+        let mut t = term(6, 4, "a\r\nb\r\nc\r\nd");
+        t.resize(3, 2);
+        assert_eq!(t.row_text(0).unwrap(), "c");
+        assert_eq!(t.row_text(1).unwrap(), "d");
+        assert_eq!(t.scrollback_len(), 2);
+        assert_eq!(t.cursor().row, 1);
+        assert_eq!(t.take_diff().rows.len(), 2);
+    }
+
+    #[test]
+    fn test_line_drawing_charset() {
+        let t = term(5, 1, "\x1b(0lqk\x1b(Bq");
+        assert_eq!(t.row_text(0).unwrap(), "┌─┐q");
+    }
+}
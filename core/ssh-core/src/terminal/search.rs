@@ -0,0 +1,292 @@
+//! Поиск по scrollback и видимому экрану и извлечение текста областей.
+//!
+//! Строки нумеруются сквозь историю и экран: 0 — самая старая строка scrollback, за последней
+//! строкой истории идёт верхняя строка экрана. Когда старые строки вытесняются из scrollback,
+//! номера сдвигаются, поэтому после `feed` совпадения нужно искать заново.
+
+use super::{flags, Cell, Color, Row, Terminal};
+use crate::{SshError, SshErrorCode};
+use regex::RegexBuilder;
+
+/// Ячейка в сквозной нумерации строк
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub col: u16,
+}
+
+/// Совпадение от `start` до `end` (не включая); в перенесённой строке может занимать несколько строк
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub start: Position,
+    pub end: Position,
+    pub text: String,
+}
+
+/// По умолчанию — буквальная строка без учёта регистра
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Запрос — регулярное выражение в синтаксисе crate `regex`
+    pub regex: bool,
+    pub case_sensitive: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextFormat {
+    Plain,
+    /// С атрибутами в виде SGR-последовательностей, сброс в конце
+    Ansi,
+}
+
+/// Строка вывода, разбитая автопереносом на несколько строк экрана
+struct LogicalLine {
+    text: String,
+    /// Байтовое смещение каждого символа в `text` и его ячейка
+    chars: Vec<(usize, Position, u8)>,
+}
+
+impl Terminal {
+    /// Строк в истории и на экране вместе
+    pub fn line_count(&self) -> usize {
+        self.screen.scrollback.len() + self.screen.grid.len()
+    }
+
+    /// Ячейки строки в сквозной нумерации
+    pub fn line(&self, line: usize) -> Option<&[Cell]> {
+        self.row_at(line).map(|r| r.cells.as_slice())
+    }
+
+    /// Все непустые совпадения по порядку; некорректное регулярное выражение — `BadRequest`
+    pub fn search(
+        &self,
+        query: &str,
+        options: SearchOptions,
+    ) -> Result<Vec<SearchMatch>, SshError> {
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pattern = if options.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .map_err(|e| {
+                SshError::new(
+                    SshErrorCode::BadRequest,
+                    format!("Invalid search pattern: {e}"),
+                    false,
+                )
+            })?;
+
+        let mut matches = Vec::new();
+        let mut line = 0;
+        while line < self.line_count() {
+            let (logical, next) = self.logical_line(line);
+            for m in regex.find_iter(&logical.text).filter(|m| !m.is_empty()) {
+                let first = logical.chars.partition_point(|c| c.0 < m.start());
+                let last = logical.chars.partition_point(|c| c.0 < m.end()) - 1;
+                let (_, start, _) = logical.chars[first];
+                let (_, end, width) = logical.chars[last];
+                matches.push(SearchMatch {
+                    start,
+                    end: Position {
+                        line: end.line,
+                        col: end.col + u16::from(width),
+                    },
+                    text: m.as_str().to_string(),
+                });
+            }
+            line = next;
+        }
+        Ok(matches)
+    }
+
+    /// Текст от `start` до `end` (не включая) в порядке вывода, как при выделении мышью:
+    /// перенесённые строки склеиваются, хвостовые пробелы строк отбрасываются
+    pub fn extract(&self, start: Position, end: Position, format: TextFormat) -> String {
+        let mut out = String::new();
+        let mut pen = Cell::default();
+        let last_line = end.line.min(self.line_count().saturating_sub(1));
+        for line in start.line..=last_line {
+            let Some(row) = self.row_at(line) else {
+                break;
+            };
+            let from = if line == start.line {
+                usize::from(start.col)
+            } else {
+                0
+            };
+            let to = if line == end.line {
+                usize::from(end.col).min(row.cells.len())
+            } else {
+                row.cells.len()
+            };
+            // Начало на второй половине широкого символа захватывает символ целиком
+            let from = if row.cells.get(from).is_some_and(|c| c.width == 0) {
+                from - 1
+            } else {
+                from
+            };
+            let mut cells = &row.cells[from.min(to)..to];
+            let line_end = to == row.cells.len() && !row.wrapped;
+            if line_end {
+                while let [rest @ .., last] = cells {
+                    if !is_trailing_blank(last, format) {
+                        break;
+                    }
+                    cells = rest;
+                }
+            }
+            for cell in cells.iter().filter(|c| c.width > 0) {
+                if format == TextFormat::Ansi && !same_attributes(cell, &pen) {
+                    out.push_str(&sgr(cell));
+                    pen = *cell;
+                }
+                out.push(cell.ch);
+            }
+            if line_end && line < last_line {
+                out.push('\n');
+            }
+        }
+        if format == TextFormat::Ansi && !same_attributes(&pen, &Cell::default()) {
+            out.push_str("\x1b[0m");
+        }
+        out
+    }
+
+    fn row_at(&self, line: usize) -> Option<&Row> {
+        let history = self.screen.scrollback.len();
+        if line < history {
+            self.screen.scrollback.get(line)
+        } else {
+            self.screen.grid.get(line - history)
+        }
+    }
+
+    /// Строка вывода, начинающаяся с `line`, и номер следующей
+    fn logical_line(&self, line: usize) -> (LogicalLine, usize) {
+        let mut logical = LogicalLine {
+            text: String::new(),
+            chars: Vec::new(),
+        };
+        let mut next = line;
+        while let Some(row) = self.row_at(next) {
+            for (col, cell) in row.cells.iter().enumerate().filter(|(_, c)| c.width > 0) {
+                let position = Position {
+                    line: next,
+                    col: col as u16,
+                };
+                logical
+                    .chars
+                    .push((logical.text.len(), position, cell.width));
+                logical.text.push(cell.ch);
+            }
+            next += 1;
+            if !row.wrapped {
+                break;
+            }
+        }
+        let trimmed = logical.text.trim_end_matches(' ').len();
+        logical.text.truncate(trimmed);
+        logical.chars.retain(|c| c.0 < trimmed);
+        (logical, next)
+    }
+}
+
+/// В ANSI пробел с фоном или инверсией видим и не отбрасывается
+fn is_trailing_blank(cell: &Cell, format: TextFormat) -> bool {
+    cell.ch == ' '
+        && (format == TextFormat::Plain
+            || (cell.bg == Color::Default
+                && cell.flags & (flags::INVERSE | flags::UNDERLINE | flags::STRIKETHROUGH) == 0))
+}
+
+fn same_attributes(a: &Cell, b: &Cell) -> bool {
+    a.fg == b.fg && a.bg == b.bg && a.flags == b.flags
+}
+
+/// Полный набор атрибутов ячейки со сбросом в начале
+fn sgr(cell: &Cell) -> String {
+    const FLAG_CODES: [(u16, u8); 8] = [
+        (flags::BOLD, 1),
+        (flags::DIM, 2),
+        (flags::ITALIC, 3),
+        (flags::UNDERLINE, 4),
+        (flags::BLINK, 5),
+        (flags::INVERSE, 7),
+        (flags::HIDDEN, 8),
+        (flags::STRIKETHROUGH, 9),
+    ];
+    let mut out = String::from("\x1b[0");
+    for (flag, code) in FLAG_CODES {
+        if cell.flags & flag != 0 {
+            out.push_str(&format!(";{code}"));
+        }
+    }
+    for (color, base) in [(cell.fg, 30), (cell.bg, 40)] {
+        match color {
+            Color::Default => {}
+            Color::Indexed(n @ 0..=7) => out.push_str(&format!(";{}", base + n)),
+            Color::Indexed(n @ 8..=15) => out.push_str(&format!(";{}", base + 60 + n - 8)),
+            Color::Indexed(n) => out.push_str(&format!(";{};5;{n}", base + 8)),
+            Color::Rgb(r, g, b) => out.push_str(&format!(";{};2;{r};{g};{b}", base + 8)),
+        }
+    }
+    out.push('m');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(line: usize, col: u16) -> Position {
+        Position { line, col }
+    }
+
+    #[test]
+    fn test_search_spans_scrollback_and_wrapped_lines() {
+        let mut t = Terminal::new(6, 2);
+        t.feed(b"Error one\r\nok\r\nan error");
+        // `Error ` и `one` — одна строка вывода, перенесённая на две строки экрана
+        assert_eq!(t.line_count(), 5);
+        assert_eq!(t.scrollback_len(), 3);
+
+        let found = t.search("error", SearchOptions::default()).unwrap();
+        assert_eq!(
+            found.iter().map(|m| (m.start, m.end)).collect::<Vec<_>>(),
+            vec![(pos(0, 0), pos(0, 5)), (pos(3, 3), pos(4, 2))]
+        );
+        assert_eq!(found[1].text, "error");
+
+        let options = SearchOptions {
+            regex: true,
+            case_sensitive: true,
+        };
+        let found = t.search(r"E\w+ o", options).unwrap();
+        assert_eq!((found[0].start, found[0].end), (pos(0, 0), pos(1, 1)));
+        assert!(t.search("error", options).unwrap().len() == 1);
+
+        let err = t.search("(", options).unwrap_err();
+        assert_eq!(err.code, SshErrorCode::BadRequest);
+    }
+
+    #[test]
+    fn test_extract_plain_and_ansi() {
+        let mut t = Terminal::new(10, 3);
+        t.feed("\x1b[1;31mred\x1b[0m 漢字\r\n\x1b[38;5;200mpink   \x1b[0m".as_bytes());
+        assert_eq!(
+            t.extract(pos(0, 0), pos(1, 10), TextFormat::Plain),
+            "red 漢字\npink"
+        );
+        // Начало на второй половине `漢` захватывает весь символ
+        assert_eq!(t.extract(pos(0, 5), pos(0, 8), TextFormat::Plain), "漢字");
+        assert_eq!(
+            t.extract(pos(0, 0), pos(1, 4), TextFormat::Ansi),
+            "\x1b[0;1;31mred\x1b[0m 漢字\n\x1b[0;38;5;200mpink\x1b[0m"
+        );
+    }
+}
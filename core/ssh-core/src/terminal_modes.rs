@@ -0,0 +1,148 @@
+//! Режимы терминала для запроса `pty-req` (RFC 4254 §8).
+
+use std::collections::BTreeMap;
+
+/// Опкоды режимов терминала (RFC 4254 §8, RFC 8160)
+pub mod opcode {
+    pub const VINTR: u8 = 1;
+    pub const VQUIT: u8 = 2;
+    pub const VERASE: u8 = 3;
+    pub const VKILL: u8 = 4;
+    pub const VEOF: u8 = 5;
+    pub const VSUSP: u8 = 10;
+    pub const ICRNL: u8 = 36;
+    pub const IXON: u8 = 38;
+    pub const IUTF8: u8 = 42;
+    pub const ISIG: u8 = 50;
+    pub const ICANON: u8 = 51;
+    pub const ECHO: u8 = 53;
+    pub const ECHOE: u8 = 54;
+    pub const ECHOK: u8 = 55;
+    pub const IEXTEN: u8 = 59;
+    pub const OPOST: u8 = 70;
+    pub const ONLCR: u8 = 72;
+    pub const CS8: u8 = 91;
+    pub const TTY_OP_ISPEED: u8 = 128;
+    pub const TTY_OP_OSPEED: u8 = 129;
+}
+
+const TTY_OP_END: u8 = 0;
+
+/// Скорость линии, которую сообщает OpenSSH по умолчанию
+const DEFAULT_SPEED: u32 = 38_400;
+
+/// Символ, который клавиша Backspace шлёт на этой платформе: классическая консоль Windows — `^H`,
+/// остальные терминалы — DEL
+#[cfg(windows)]
+const PLATFORM_ERASE: u32 = 0x08;
+#[cfg(not(windows))]
+const PLATFORM_ERASE: u32 = 0x7f;
+
+/// Набор режимов «опкод → значение»; в `pty-req` уходит в порядке опкодов
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TerminalModes(BTreeMap<u8, u32>);
+
+impl TerminalModes {
+    /// Пустой набор: сервер применит свои значения
+    pub fn empty() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// Канонический ввод с эхом, сигналы, UTF-8 и символ стирания этой платформы
+    pub fn platform_default() -> Self {
+        use opcode::*;
+        let mut modes = Self::empty();
+        for (op, value) in [
+            (VINTR, 0x03),
+            (VQUIT, 0x1c),
+            (VERASE, PLATFORM_ERASE),
+            (VKILL, 0x15),
+            (VEOF, 0x04),
+            (VSUSP, 0x1a),
+            (ICRNL, 1),
+            (IXON, 0),
+            (IUTF8, 1),
+            (ISIG, 1),
+            (ICANON, 1),
+            (ECHO, 1),
+            (ECHOE, 1),
+            (ECHOK, 1),
+            (IEXTEN, 1),
+            (OPOST, 1),
+            (ONLCR, 1),
+            (CS8, 1),
+            (TTY_OP_ISPEED, DEFAULT_SPEED),
+            (TTY_OP_OSPEED, DEFAULT_SPEED),
+        ] {
+            modes.set(op, value);
+        }
+        modes
+    }
+
+    /// `TTY_OP_END` и опкоды вне RFC игнорируются: их нельзя закодировать однозначно
+    pub fn set(&mut self, opcode: u8, value: u32) -> &mut Self {
+        if opcode != TTY_OP_END && opcode < 160 {
+            self.0.insert(opcode, value);
+        }
+        self
+    }
+
+    pub fn get(&self, opcode: u8) -> Option<u32> {
+        self.0.get(&opcode).copied()
+    }
+
+    pub fn remove(&mut self, opcode: u8) -> Option<u32> {
+        self.0.remove(&opcode)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u8, u32)> + '_ {
+        self.0.iter().map(|(&op, &value)| (op, value))
+    }
+
+    /// Строка `encoded terminal modes` из `pty-req`, завершённая `TTY_OP_END`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.0.len() * 5 + 1);
+        for (op, value) in self.iter() {
+            out.push(op);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        out.push(TTY_OP_END);
+        out
+    }
+
+    /// Опкоды, которых нет в russh, не отправляются
+    pub(crate) fn to_russh(&self) -> Vec<(russh::Pty, u32)> {
+        self.iter()
+            .filter_map(|(op, value)| Some((russh::Pty::from_u8(op)?, value)))
+            .collect()
+    }
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        Self::platform_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::opcode::*;
+    use super::*;
+
+    #[test]
+    fn test_platform_defaults_and_encoding() {
+        let mut modes = TerminalModes::platform_default();
+        assert_eq!(modes.get(VERASE), Some(PLATFORM_ERASE));
+        assert_eq!(modes.get(IUTF8), Some(1));
+        assert_eq!(modes.get(TTY_OP_OSPEED), Some(38_400));
+        assert_eq!(modes.to_russh().len(), modes.iter().count());
+
+        modes.set(TTY_OP_END, 1);
+        assert_eq!(modes.get(TTY_OP_END), None);
+
+        let mut small = TerminalModes::empty();
+        small.set(ECHO, 0).set(VINTR, 3);
+        assert_eq!(small.encode(), [1, 0, 0, 0, 3, 53, 0, 0, 0, 0, 0]);
+        assert_eq!(TerminalModes::empty().encode(), [0]);
+    }
+}
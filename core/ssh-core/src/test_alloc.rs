@@ -0,0 +1,73 @@
+//! Глобальный аллокатор тестов: считает по потокам освобождённые блоки, в которых остался [`SECRET`],
+//! и пик занятой потоком памяти. Так затирание проверяется без чтения за `Vec::len` живых
+//! буферов, а ограниченность буферов — на живой сессии (`#[tokio::test]` идёт в одном потоке).
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use zeroize::Zeroizing;
+
+/// Метка секрета в тестовых данных; в освобождённой памяти её быть не должно
+pub(crate) const SECRET: &[u8] = b"s3cr3t-7c1e9a";
+
+thread_local! {
+    /// Счёт по потокам: параллельный тест не приписывает свои утечки чужому
+    static LEAKED: Cell<usize> = const { Cell::new(0) };
+    /// Выделено минус освобождено в этом потоке; блок, освобождённый в другом потоке, занижает счёт
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+struct WipeCheck;
+
+// SAFETY: память выдаёт `System`; блоки обнуляются при выделении, поэтому при освобождении
+// все их байты инициализированы. `realloc` по умолчанию проходит через `alloc` и `dealloc`,
+// так что проверяется и старая копия при росте буфера
+unsafe impl GlobalAlloc for WipeCheck {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        account(layout.size() as isize);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        account(-(layout.size() as isize));
+        let block = std::slice::from_raw_parts(ptr, layout.size());
+        if block.windows(SECRET.len()).any(|w| w == SECRET) {
+            let _ = LEAKED.try_with(|leaked| leaked.set(leaked.get() + 1));
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: WipeCheck = WipeCheck;
+
+/// `try_with`: поток может освобождать память уже после удаления своих thread-local
+fn account(bytes: isize) {
+    let _ = LIVE.try_with(|live| {
+        let now = live.get() + bytes;
+        live.set(now);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(now)));
+    });
+}
+
+/// Начинает замер пика: возвращает текущий уровень, от которого его считать
+pub(crate) fn start_peak() -> isize {
+    let now = LIVE.with(Cell::get);
+    PEAK.with(|peak| peak.set(now));
+    now
+}
+
+/// Наибольший уровень памяти потока с последнего [`start_peak`]
+pub(crate) fn peak() -> isize {
+    PEAK.with(Cell::get)
+}
+
+/// Сколько освобождённых этим потоком блоков содержали [`SECRET`]
+pub(crate) fn leaked() -> usize {
+    LEAKED.with(Cell::get)
+}
+
+/// `prefix` + [`SECRET`] + `suffix` одним выделением, без промежуточных копий
+pub(crate) fn with_secret(prefix: &[u8], suffix: &[u8]) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new([prefix, SECRET, suffix].concat())
+}
@@ -0,0 +1,262 @@
+//! SSH-сервер в процессе теста: принимает любой пароль, открывает PTY и exec и записывает,
+//! что прислал клиент. Нужен там, где поведение зависит от живого цикла russh.
+
+use crate::{HostKeyPolicy, SshSession};
+use russh::server::{self, Auth, Msg, Session};
+use russh::{Channel, ChannelId, CryptoVec};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+
+const OUTPUT_CHUNK: usize = 8 * 1024;
+
+type HandlerFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, russh::Error>> + Send + 'a>>;
+
+/// Что сервер получил от клиента: запросы каналов (`"shell 0"`, `"exec 1 true"`,
+/// `"close 0"`) и склеенный stdin всех каналов
+#[derive(Default)]
+pub(crate) struct ServerLog {
+    requests: Mutex<Vec<String>>,
+    stdin: Mutex<Vec<u8>>,
+    changed: Notify,
+}
+
+impl ServerLog {
+    fn record(&self, request: String) {
+        self.requests.lock().expect("poisoned").push(request);
+        self.changed.notify_waiters();
+    }
+
+    /// Ждёт, пока записанное не удовлетворит условию
+    pub(crate) async fn wait_until(&self, done: impl Fn(&[String], &[u8]) -> bool) {
+        loop {
+            let changed = self.changed.notified();
+            if done(
+                &self.requests.lock().expect("poisoned"),
+                &self.stdin.lock().expect("poisoned"),
+            ) {
+                return;
+            }
+            changed.await;
+        }
+    }
+}
+
+pub(crate) struct TestServer {
+    port: u16,
+    pub(crate) log: Arc<ServerLog>,
+}
+
+impl TestServer {
+    /// На каждый запрос `shell` или `exec` сервер отвечает `output` байтами `x`
+    pub(crate) async fn start(output: usize) -> Self {
+        let key = ssh_key::PrivateKey::random(&mut rand::rngs::OsRng, ssh_key::Algorithm::Ed25519)
+            .expect("host key");
+        let config = Arc::new(server::Config {
+            keys: vec![key],
+            auth_rejection_time: std::time::Duration::ZERO,
+            ..server::Config::default()
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+        listener.set_nonblocking(true).expect("nonblocking");
+        let port = listener.local_addr().expect("addr").port();
+        let log = Arc::new(ServerLog::default());
+
+        // Свой поток и runtime: память и планирование сервера не смешиваются с клиентом теста
+        let server_log = Arc::clone(&log);
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("runtime");
+            runtime.block_on(async move {
+                let listener = TcpListener::from_std(listener).expect("listener");
+                while let Ok((socket, _)) = listener.accept().await {
+                    let handler = Handler {
+                        log: Arc::clone(&server_log),
+                        output,
+                    };
+                    let config = Arc::clone(&config);
+                    tokio::spawn(async move {
+                        if let Ok(running) = server::run_stream(config, socket, handler).await {
+                            let _ = running.await;
+                        }
+                    });
+                }
+            });
+        });
+        Self { port, log }
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Сессия после проверки ключа и входа по паролю, каналов ещё нет
+    pub(crate) async fn session(&self) -> SshSession {
+        let mut session = SshSession::connect("127.0.0.1", self.port, "tester", 5_000)
+            .await
+            .expect("connect");
+        session
+            .verify_host_key(HostKeyPolicy::AcceptNew, None)
+            .await
+            .expect("host key");
+        session
+            .auth_password("secret".to_string().into())
+            .await
+            .expect("auth");
+        session
+    }
+}
+
+struct Handler {
+    log: Arc<ServerLog>,
+    output: usize,
+}
+
+impl Handler {
+    fn start_output(&self, channel: ChannelId, session: &mut Session) {
+        let handle = session.handle();
+        let mut left = self.output;
+        tokio::spawn(async move {
+            while left > 0 {
+                let chunk = left.min(OUTPUT_CHUNK);
+                if handle
+                    .data(channel, CryptoVec::from(vec![b'x'; chunk]))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                left -= chunk;
+            }
+        });
+    }
+}
+
+impl server::Handler for Handler {
+    type Error = russh::Error;
+
+    fn auth_password<'life0, 'life1, 'life2, 'async_trait>(
+        &'life0 mut self,
+        _user: &'life1 str,
+        _password: &'life2 str,
+    ) -> HandlerFuture<'async_trait, Auth>
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+    {
+        Box::pin(async { Ok(Auth::Accept) })
+    }
+
+    fn channel_open_session<'life0, 'life1, 'async_trait>(
+        &'life0 mut self,
+        _channel: Channel<Msg>,
+        _session: &'life1 mut Session,
+    ) -> HandlerFuture<'async_trait, bool>
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+    {
+        Box::pin(async { Ok(true) })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn pty_request<'life0, 'life1, 'life2, 'life3, 'async_trait>(
+        &'life0 mut self,
+        channel: ChannelId,
+        _term: &'life1 str,
+        _col_width: u32,
+        _row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _modes: &'life2 [(russh::Pty, u32)],
+        session: &'life3 mut Session,
+    ) -> HandlerFuture<'async_trait, ()>
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+        'life3: 'async_trait,
+    {
+        self.log.record(format!("pty {channel}"));
+        let result = session.channel_success(channel);
+        Box::pin(async { result })
+    }
+
+    fn shell_request<'life0, 'life1, 'async_trait>(
+        &'life0 mut self,
+        channel: ChannelId,
+        session: &'life1 mut Session,
+    ) -> HandlerFuture<'async_trait, ()>
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+    {
+        self.log.record(format!("shell {channel}"));
+        let result = session.channel_success(channel);
+        self.start_output(channel, session);
+        Box::pin(async { result })
+    }
+
+    fn exec_request<'life0, 'life1, 'life2, 'async_trait>(
+        &'life0 mut self,
+        channel: ChannelId,
+        data: &'life1 [u8],
+        session: &'life2 mut Session,
+    ) -> HandlerFuture<'async_trait, ()>
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+    {
+        self.log
+            .record(format!("exec {channel} {}", String::from_utf8_lossy(data)));
+        let result = session.channel_success(channel);
+        self.start_output(channel, session);
+        Box::pin(async { result })
+    }
+
+    fn data<'life0, 'life1, 'life2, 'async_trait>(
+        &'life0 mut self,
+        _channel: ChannelId,
+        data: &'life1 [u8],
+        _session: &'life2 mut Session,
+    ) -> HandlerFuture<'async_trait, ()>
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+    {
+        self.log
+            .stdin
+            .lock()
+            .expect("poisoned")
+            .extend_from_slice(data);
+        self.log.changed.notify_waiters();
+        Box::pin(async { Ok(()) })
+    }
+
+    fn channel_close<'life0, 'life1, 'async_trait>(
+        &'life0 mut self,
+        channel: ChannelId,
+        _session: &'life1 mut Session,
+    ) -> HandlerFuture<'async_trait, ()>
+    where
+        Self: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+    {
+        self.log.record(format!("close {channel}"));
+        Box::pin(async { Ok(()) })
+    }
+}
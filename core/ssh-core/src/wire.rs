@@ -0,0 +1,262 @@
+//! Прослушка транспорта до шифрования: идентификационные строки и оба `SSH_MSG_KEXINIT`.
+//!
+//! Видит только открытую часть рукопожатия; после первого пакета в каждую сторону не буферизует ничего.
+
+use crate::algorithms::{self, KexInit, NegotiatedAlgorithms};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Предел на строки до идентификации (RFC 4253 §4.2) и на размер пакета KEXINIT
+const MAX_SNIFF_BYTES: usize = 64 << 10;
+
+#[derive(Default)]
+struct Sniffer {
+    buf: Vec<u8>,
+    banner: Option<String>,
+    kexinit: Option<KexInit>,
+    done: bool,
+}
+
+impl Sniffer {
+    fn feed(&mut self, data: &[u8]) {
+        if self.done {
+            return;
+        }
+        self.buf.extend_from_slice(data);
+        while self.banner.is_none() {
+            let Some(end) = self.buf.iter().position(|&b| b == b'\n') else {
+                self.give_up_if_oversized();
+                return;
+            };
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.starts_with("SSH-") {
+                self.banner = Some(line.to_string());
+            }
+        }
+        let Some(len) = self.buf.get(..4) else {
+            return;
+        };
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if len > MAX_SNIFF_BYTES {
+            self.stop();
+            return;
+        }
+        if self.buf.len() < 4 + len {
+            return;
+        }
+        let padding = self.buf.get(4).copied().unwrap_or(0) as usize;
+        self.kexinit = (len > padding)
+            .then(|| KexInit::parse(&self.buf[5..4 + len - padding]))
+            .flatten();
+        self.stop();
+    }
+
+    fn give_up_if_oversized(&mut self) {
+        if self.buf.len() > MAX_SNIFF_BYTES {
+            self.stop();
+        }
+    }
+
+    fn stop(&mut self) {
+        self.done = true;
+        self.buf = Vec::new();
+    }
+}
+
+#[derive(Default)]
+struct TapState {
+    incoming: Sniffer,
+    outgoing: Sniffer,
+}
+
+/// Байты на сокете против байтов данных каналов. Отношение включает накладные расходы
+/// протокола (заголовки, MAC, keepalive), поэтому без сжатия оно чуть меньше 1
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    pub wire_bytes_sent: u64,
+    pub wire_bytes_received: u64,
+    pub payload_bytes_sent: u64,
+    pub payload_bytes_received: u64,
+}
+
+impl TrafficStats {
+    /// Во сколько раз данные stdin больше отправленного на сокет; `None`, пока ничего не отправлено
+    pub fn ratio_sent(&self) -> Option<f64> {
+        ratio(self.payload_bytes_sent, self.wire_bytes_sent)
+    }
+
+    /// Во сколько раз полученный вывод больше принятого с сокета
+    pub fn ratio_received(&self) -> Option<f64> {
+        ratio(self.payload_bytes_received, self.wire_bytes_received)
+    }
+}
+
+fn ratio(payload: u64, wire: u64) -> Option<f64> {
+    (payload > 0 && wire > 0).then(|| payload as f64 / wire as f64)
+}
+
+/// Общая для потока и сессии часть прослушки
+#[derive(Default)]
+pub(crate) struct WireTap {
+    state: Mutex<TapState>,
+    wire_sent: AtomicU64,
+    wire_received: AtomicU64,
+    payload_sent: AtomicU64,
+    payload_received: AtomicU64,
+}
+
+impl WireTap {
+    /// Итог согласования; `None`, пока обмен KEXINIT не завершён или не распознан
+    pub(crate) fn negotiated(&self) -> Option<NegotiatedAlgorithms> {
+        let state = self.state.lock().expect("poisoned");
+        algorithms::negotiate(
+            state.outgoing.kexinit.as_ref()?,
+            state.incoming.kexinit.as_ref()?,
+        )
+    }
+
+    /// Идентификационная строка сервера без `\r\n`
+    pub(crate) fn server_banner(&self) -> Option<String> {
+        self.state.lock().expect("poisoned").incoming.banner.clone()
+    }
+
+    pub(crate) fn record_payload_sent(&self, n: usize) {
+        self.payload_sent.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_payload_received(&self, n: usize) {
+        self.payload_received.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> TrafficStats {
+        TrafficStats {
+            wire_bytes_sent: self.wire_sent.load(Ordering::Relaxed),
+            wire_bytes_received: self.wire_received.load(Ordering::Relaxed),
+            payload_bytes_sent: self.payload_sent.load(Ordering::Relaxed),
+            payload_bytes_received: self.payload_received.load(Ordering::Relaxed),
+        }
+    }
+
+    fn on_read(&self, data: &[u8]) {
+        self.wire_received
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        let mut state = self.state.lock().expect("poisoned");
+        state.incoming.feed(data);
+    }
+
+    fn on_write(&self, data: &[u8]) {
+        self.wire_sent
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        let mut state = self.state.lock().expect("poisoned");
+        state.outgoing.feed(data);
+    }
+}
+
+/// Поток, сообщающий [`WireTap`] обо всех байтах в обе стороны
+pub(crate) struct Tapped<S> {
+    inner: S,
+    tap: Arc<WireTap>,
+}
+
+impl<S> Tapped<S> {
+    pub(crate) fn new(inner: S, tap: Arc<WireTap>) -> Self {
+        Self { inner, tap }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Tapped<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.tap.on_read(&buf.filled()[before..]);
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Tapped<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, data);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.tap.on_write(&data[..n]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::kexinit_payload;
+
+    fn packet(payload: &[u8]) -> Vec<u8> {
+        let padding = 4;
+        let mut out = ((payload.len() + 1 + padding) as u32)
+            .to_be_bytes()
+            .to_vec();
+        out.push(padding as u8);
+        out.extend_from_slice(payload);
+        out.extend_from_slice(&[0; 4]);
+        out
+    }
+
+    #[test]
+    fn test_sniffer_reads_banner_and_kexinit_in_pieces() {
+        let lists = [
+            "curve25519-sha256",
+            "ssh-ed25519",
+            "aes256-ctr",
+            "aes256-ctr",
+            "hmac-sha2-256",
+            "hmac-sha2-256",
+            "none",
+            "none",
+            "",
+            "",
+        ];
+        let mut wire = b"pre-banner notice\r\nSSH-2.0-OpenSSH_9.6\r\n".to_vec();
+        wire.extend(packet(&kexinit_payload(lists)));
+        wire.extend_from_slice(b"encrypted tail");
+
+        let tap = WireTap::default();
+        for chunk in wire.chunks(7) {
+            tap.on_read(chunk);
+            tap.on_write(chunk);
+        }
+        assert_eq!(tap.server_banner().as_deref(), Some("SSH-2.0-OpenSSH_9.6"));
+        let state = tap.state.lock().expect("poisoned");
+        assert!(state.incoming.done && state.incoming.buf.is_empty());
+        drop(state);
+        let negotiated = tap.negotiated().unwrap();
+        assert_eq!(negotiated.kex, "curve25519-sha256");
+        assert_eq!(negotiated.mac_client_to_server, "hmac-sha2-256");
+
+        assert_eq!(tap.stats().wire_bytes_received, wire.len() as u64);
+        assert_eq!(tap.stats().ratio_received(), None);
+        tap.record_payload_received(wire.len() * 3);
+        assert_eq!(tap.stats().ratio_received(), Some(3.0));
+    }
+}
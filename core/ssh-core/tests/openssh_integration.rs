@@ -209,12 +209,24 @@ async fn it_accept_new_accept_once_and_pty_resize() {
         .await
         .unwrap();
 
+    let info = s1.connection_info().expect("connection info");
+    assert!(info
+        .server_banner
+        .as_deref()
+        .is_some_and(|b| b.starts_with("SSH-2.0-OpenSSH")));
+    assert_eq!(info.remote_addr.port(), port);
+    assert!(info.algorithms.is_some());
+    assert!(info.auth_latency_ms.is_some());
+
     let mut rx = s1.subscribe_events();
 
     s1.open_pty(Pty {
         cols: 80,
         rows: 24,
         term: "xterm-256color".to_string(),
+        pixel_width: 640,
+        pixel_height: 384,
+        ..Pty::default()
     })
     .await
     .unwrap();